@@ -1,86 +1,673 @@
+use std::ops::Range;
+
 use rustfft::FftPlanner;
 use rustfft::num_complex::Complex;
 
-use crate::core::{NoteSeries, usize_to_f64, usize_to_isize};
-
-pub fn compute_time_shift(target: &NoteSeries, input: &NoteSeries) -> Option<isize> {
-    let n = target.len();
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(n);
-    let ifft = planner.plan_fft_inverse(n);
+use crate::core::{
+    Note, NoteKind, NoteSeries, f64_to_usize, isize_to_f64, median, usize_to_f64, usize_to_isize,
+};
 
-    let target_values = target
+/// Alignment feature of every sample of `series`, zero padded to `len` samples.
+///
+/// The real part is whether the sample is voiced and the imaginary part is its note number
+/// relative to the mean of the voiced samples, each scaled to unit energy over the series. The
+/// real part of the correlation of two features is then the sum of the normalised correlations
+/// of when the series are voiced and of the shape of their melodies, regardless of their key.
+fn alignment_feature(series: &NoteSeries, len: usize) -> Option<Vec<Complex<f64>>> {
+    let voiced = series
         .iter()
-        .map(|v| v.as_ref().map_or(0., |x| x.note_number))
-        .collect::<Box<_>>();
-    let input_values = input
+        .flatten()
+        .map(|n| n.note_number)
+        .collect::<Vec<_>>();
+    if voiced.is_empty() {
+        return None;
+    }
+    let count = usize_to_f64(voiced.len());
+    let mean = voiced.iter().sum::<f64>() / count;
+    let pitch_energy = voiced
         .iter()
-        .map(|v| v.as_ref().map_or(0., |x| x.note_number))
-        .collect::<Box<_>>();
+        .map(|n| (n - mean).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    let voicing_scale = count.sqrt().recip();
+    let pitch_scale = if pitch_energy > 0. {
+        pitch_energy.recip()
+    } else {
+        0.
+    };
 
-    let mut target_fft = target_values
-        .iter()
-        .map(|&x| Complex::new(x, 0.))
-        .collect::<Box<_>>();
-    let mut input_fft = input_values
+    let mut feature = series
         .iter()
-        .map(|&x| Complex::new(x, 0.))
-        .collect::<Box<_>>();
+        .map(|v| {
+            v.as_ref().map_or_else(Complex::default, |n| {
+                Complex::new(voicing_scale, (n.note_number - mean) * pitch_scale)
+            })
+        })
+        .collect::<Vec<_>>();
+    feature.resize(len, Complex::default());
+    Some(feature)
+}
+
+/// Finds how many samples, to a fraction of a sample, the target should be shifted left to line
+/// up with the input, by linear cross-correlation of their [alignment
+/// features](alignment_feature), or `None` if either has no notes.
+///
+/// Both series are zero padded to at least twice the length of the longer one before the FFT,
+/// so the correlation does not wrap around and a shift can be as long as the series themselves.
+pub fn compute_time_shift(target: &NoteSeries, input: &NoteSeries) -> Option<f64> {
+    correlate(target, input).map(|(shift, _)| shift)
+}
+
+/// Finds the shift as [`compute_time_shift`] does, along with the correlation at that shift.
+fn correlate(target: &NoteSeries, input: &NoteSeries) -> Option<(f64, f64)> {
+    let n = target.len().max(input.len());
+    let len = (2 * n).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(len);
+    let ifft = planner.plan_fft_inverse(len);
+
+    let mut target_fft = alignment_feature(target, len)?;
+    let mut input_fft = alignment_feature(input, len)?;
 
     fft.process(&mut target_fft);
     fft.process(&mut input_fft);
 
-    for i in 0..n {
-        input_fft[i] = input_fft[i].conj() * target_fft[i];
+    for (i, t) in input_fft.iter_mut().zip(&target_fft) {
+        *i = i.conj() * t;
     }
 
     ifft.process(&mut input_fft);
 
-    let (max_shift, _) = input_fft
+    let (max_shift, peak) = input_fft
         .iter()
         .enumerate()
         .max_by(|(_, a), (_, b)| a.re.total_cmp(&b.re))?;
 
-    if max_shift < n / 2 {
-        Some(usize_to_isize(max_shift))
+    // fit a parabola through the peak and its neighbours for the shift between frames
+    let before = input_fft[(max_shift + len - 1) % len].re;
+    let after = input_fft[(max_shift + 1) % len].re;
+    let curvature = (-2f64).mul_add(peak.re, before + after);
+    let fraction = if curvature < 0. {
+        (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.
+    };
+
+    let lag = if max_shift < len / 2 {
+        usize_to_isize(max_shift)
     } else {
-        Some(usize_to_isize(max_shift) - usize_to_isize(n))
+        usize_to_isize(max_shift) - usize_to_isize(len)
+    };
+    // the inverse FFT is not normalised
+    Some((isize_to_f64(lag) + fraction, peak.re / usize_to_f64(len)))
+}
+
+/// Range of the ratio of the sung tempo to the tempo of the target that is searched.
+#[derive(Debug, Clone)]
+pub struct TempoOptions {
+    pub min_ratio: f64,
+    pub max_ratio: f64,
+}
+
+impl Default for TempoOptions {
+    fn default() -> Self {
+        Self {
+            min_ratio: 0.9,
+            max_ratio: 1.1,
+        }
+    }
+}
+
+impl TempoOptions {
+    /// Whether the range is finite, positive and not inverted, so that it can be searched.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        0. < self.min_ratio && self.min_ratio <= self.max_ratio && self.max_ratio.is_finite()
+    }
+}
+
+/// Steps of the tempo ratios searched at every resolution, each around the best ratio found at
+/// the resolution before.
+const TEMPO_RATIO_STEPS: [f64; 2] = [0.01, 0.001];
+
+/// Finds the ratio of the sung tempo to the tempo of the target, and the time shift at that
+/// tempo, that line the target up with the input best, or `None` if either has no notes or the
+/// range of ratios [is not valid](TempoOptions::is_valid).
+///
+/// `stretch` samples the target at a tempo ratio. The ratios are searched coarsely across the
+/// whole range first and then finely around the best of them, correlating the stretched target
+/// with the input as [`compute_time_shift`] does.
+pub fn compute_tempo_ratio(
+    stretch: impl Fn(f64) -> Vec<Option<Note>>,
+    input: &NoteSeries,
+    options: &TempoOptions,
+) -> Option<(f64, f64)> {
+    if !options.is_valid() {
+        return None;
+    }
+    let mut best = None::<(f64, f64, f64)>;
+    let (mut low, mut high) = (options.min_ratio, options.max_ratio);
+    for step in TEMPO_RATIO_STEPS {
+        for k in 0..=f64_to_usize(((high - low) / step).round()) {
+            let ratio = step.mul_add(usize_to_f64(k), low);
+            let Some((shift, score)) = correlate(&stretch(ratio), input) else {
+                continue;
+            };
+            if best.is_none_or(|(.., best_score)| score > best_score) {
+                best = Some((ratio, shift, score));
+            }
+        }
+        let (ratio, ..) = best?;
+        low = (ratio - step).max(options.min_ratio);
+        high = (ratio + step).min(options.max_ratio);
+    }
+    best.map(|(ratio, shift, _)| (ratio, shift))
+}
+
+/// Moves that a warping path may take from one pair of input and target frames to the next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StepPattern {
+    /// The input, the target or both advance by a frame, each adding the cost of the pair
+    /// reached.
+    Symmetric1,
+    /// As [`Self::Symmetric1`], but advancing both adds the cost twice so that a diagonal step
+    /// costs as much as the two steps it cuts across.
+    #[default]
+    Symmetric2,
+    /// The input advances by a frame every step while the target advances by none, one or two,
+    /// so that every sung frame is matched with exactly one target frame.
+    Asymmetric,
+}
+
+impl StepPattern {
+    /// Frames the input and the target advance by in each step, and the weight of the cost of
+    /// the pair reached.
+    const fn steps(self) -> &'static [(usize, usize, f64)] {
+        match self {
+            Self::Symmetric1 => &[(1, 1, 1.), (1, 0, 1.), (0, 1, 1.)],
+            Self::Symmetric2 => &[(1, 1, 2.), (1, 0, 1.), (0, 1, 1.)],
+            Self::Asymmetric => &[(1, 1, 1.), (1, 0, 1.), (1, 2, 1.)],
+        }
     }
 }
 
-pub fn apply_time_shift(target: &mut NoteSeries, shift: isize) {
-    match shift.cmp(&0) {
-        std::cmp::Ordering::Greater => target.rotate_left(shift.unsigned_abs()),
-        std::cmp::Ordering::Less => target.rotate_right(shift.unsigned_abs()),
-        std::cmp::Ordering::Equal => {}
+/// How the target is warped onto the input by dynamic time warping after it is shifted.
+#[derive(Debug, Clone)]
+pub struct WarpOptions {
+    /// Seconds that a sung frame may be matched earlier or later than the shifted target.
+    pub band_secs: f64,
+    pub step_pattern: StepPattern,
+}
+
+impl Default for WarpOptions {
+    fn default() -> Self {
+        Self {
+            band_secs: 1.,
+            step_pattern: StepPattern::default(),
+        }
+    }
+}
+
+/// Semitones of pitch distance at which a pair of frames costs as much as a note against a rest.
+const MAX_WARP_COST: f64 = 2.;
+
+/// Cost of a step that does not advance the input and the target alike, so that the path only
+/// leaves the diagonal where it follows the singing more closely.
+const OFF_DIAGONAL_COST: f64 = 0.1;
+
+fn warp_cost(target: Option<&Note>, input: Option<&Note>) -> f64 {
+    match (target, input) {
+        (Some(t), Some(i)) => t.distance(i.note_number).min(MAX_WARP_COST),
+        (None, None) => 0.,
+        _ => MAX_WARP_COST,
     }
 }
 
-pub fn compute_note_shift(target: &NoteSeries, input: &NoteSeries) -> f64 {
+/// Finds the target frame matched with every input frame by the warping path of least cost that
+/// stays within `band` frames of the diagonal.
+pub fn compute_warp(
+    target: &NoteSeries,
+    input: &NoteSeries,
+    band: usize,
+    step_pattern: StepPattern,
+) -> Box<[usize]> {
     assert_eq!(
         target.len(),
         input.len(),
         "Signals must have the same length"
     );
+    let n = input.len();
+    if n == 0 {
+        return Box::default();
+    }
+    let width = 2 * band + 1;
+    // the pair of input frame `i` and target frame `j` is stored at `i * width + j + band - i`
+    let cell = |i: usize, j: usize| {
+        (j + band)
+            .checked_sub(i)
+            .filter(|&k| k < width && j < n)
+            .map(|k| i * width + k)
+    };
+    let pattern = step_pattern.steps();
+    let mut costs = vec![f64::INFINITY; n * width];
+    let mut steps = vec![0u8; n * width];
+    for (i, sung) in input.iter().enumerate() {
+        let start = i.saturating_sub(band);
+        for (j, expected) in target.iter().enumerate().take(i + band + 1).skip(start) {
+            let Some(c) = cell(i, j) else {
+                continue;
+            };
+            let cost = warp_cost(expected.as_ref(), sung.as_ref());
+            if i == 0 && j == 0 {
+                costs[c] = cost;
+                continue;
+            }
+            let best = pattern
+                .iter()
+                .zip(0u8..)
+                .filter_map(|(&(di, dj, weight), step)| {
+                    let previous = cell(i.checked_sub(di)?, j.checked_sub(dj)?)?;
+                    let penalty = if di == dj { 0. } else { OFF_DIAGONAL_COST };
+                    Some((step, weight.mul_add(cost, costs[previous] + penalty)))
+                })
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+            if let Some((step, total)) = best {
+                costs[c] = total;
+                steps[c] = step;
+            }
+        }
+    }
 
-    let mut sum_diff = 0.;
-    let mut count = 0usize;
-
-    for (t, i) in target.iter().zip(input.iter()) {
-        if let (Some(t_val), Some(i_val)) = (t, i) {
-            sum_diff += i_val.note_number - t_val.note_number;
-            count += 1;
+    let mut path = vec![0; n];
+    let (mut i, mut j) = (n - 1, n - 1);
+    while let Some(c) = cell(i, j) {
+        path[i] = j;
+        if i == 0 && j == 0 {
+            break;
         }
+        let (di, dj, _) = pattern[usize::from(steps[c])];
+        i -= di;
+        j -= dj;
+    }
+    path.into()
+}
+
+/// Replaces every frame of the target with the target frame that `path` matches it with.
+pub fn apply_warp(target: &mut NoteSeries, path: &[usize]) {
+    let original = target.to_vec();
+    for (sample, &j) in target.iter_mut().zip(path) {
+        sample.clone_from(&original[j]);
+    }
+}
+
+/// How far the input is sung from the key of the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyShift {
+    /// Semitones the input is sung above the target, or below when negative.
+    pub semitones: f64,
+    /// Fraction of the sung frames of the target whose shift agrees with it in any octave.
+    pub confidence: f64,
+}
+
+/// Width in semitones of a bin of the histogram of the shifts within an octave.
+const KEY_BIN_WIDTH: f64 = 0.1;
+/// Bins either side of the most common shift whose frames agree with it.
+const KEY_BIN_RADIUS: usize = 5;
+
+/// Finds the key shift of the input from the target, or `None` if no sung frame is on a note of
+/// the target whose pitch is graded.
+///
+/// The shift within an octave is the mode of a circular histogram of the shifts of every frame,
+/// refined by the mean of the shifts that agree with it, and the octave is the median octave of
+/// those. Passages sung an octave off or on the wrong notes then do not pull the shift away.
+pub fn compute_note_shift(target: &NoteSeries, input: &NoteSeries) -> Option<KeyShift> {
+    assert_eq!(
+        target.len(),
+        input.len(),
+        "Signals must have the same length"
+    );
+
+    let differences = target
+        .iter()
+        .zip(input)
+        .filter_map(|(t, i)| match (t, i) {
+            (Some(t), Some(i)) if t.kind != NoteKind::Freestyle => {
+                Some(i.note_number - t.note_number)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if differences.is_empty() {
+        return None;
     }
 
-    debug_assert_ne!(count, 0, "No valid samples to compute amplitude shift");
-    sum_diff / usize_to_f64(count)
+    let bins = f64_to_usize((12. / KEY_BIN_WIDTH).round());
+    let mut histogram = vec![0usize; bins];
+    for d in &differences {
+        histogram[f64_to_usize((d.rem_euclid(12.) / KEY_BIN_WIDTH).floor()) % bins] += 1;
+    }
+    // smoothed with a triangular window so that the mode is at the middle of a cluster of shifts
+    let mode = (0..bins).max_by_key(|&bin| {
+        (0..=2 * KEY_BIN_RADIUS)
+            .map(|k| {
+                let weight = KEY_BIN_RADIUS + 1 - k.abs_diff(KEY_BIN_RADIUS);
+                weight * histogram[(bin + bins + k - KEY_BIN_RADIUS) % bins]
+            })
+            .sum::<usize>()
+    })?;
+    let centre = (usize_to_f64(mode) + 0.5) * KEY_BIN_WIDTH;
+    let tolerance = (usize_to_f64(KEY_BIN_RADIUS) + 0.5) * KEY_BIN_WIDTH;
+
+    // the shift of every agreeing frame from the mode, in the octave nearest to it
+    let agreeing = differences
+        .iter()
+        .filter_map(|&d| {
+            let offset = (d - centre + 6.).rem_euclid(12.) - 6.;
+            (offset.abs() <= tolerance).then_some((d, offset))
+        })
+        .collect::<Vec<_>>();
+    let count = usize_to_f64(agreeing.len());
+    let class = centre + agreeing.iter().map(|(_, offset)| offset).sum::<f64>() / count;
+    let mut octaves = agreeing
+        .iter()
+        .map(|(d, offset)| ((d - offset - centre) / 12.).round())
+        .collect::<Vec<_>>();
+    octaves.sort_unstable_by(f64::total_cmp);
+
+    Some(KeyShift {
+        semitones: median(&octaves).round().mul_add(12., class),
+        confidence: count / usize_to_f64(differences.len()),
+    })
 }
 
 pub fn apply_note_shift(target: &mut NoteSeries, shift: f64) {
     for sample in target {
-        if let Some(note_number) = sample.as_mut() {
-            note_number.note_number += shift;
+        if let Some(note) = sample.as_mut() {
+            note.note_number += shift;
+            for alternative in &mut note.alternatives {
+                *alternative += shift;
+            }
+        }
+    }
+}
+
+/// How the target is split into phrases at its rests, each aligned on its own after the whole
+/// target is shifted.
+#[derive(Debug, Clone)]
+pub struct PhraseOptions {
+    /// Seconds of rest in the target that end a phrase.
+    pub min_rest_secs: f64,
+    /// Seconds that a phrase may be sung earlier or later than the shifted target.
+    pub search_secs: f64,
+}
+
+impl Default for PhraseOptions {
+    fn default() -> Self {
+        Self {
+            min_rest_secs: 0.25,
+            search_secs: 0.5,
+        }
+    }
+}
+
+/// A phrase of the target as it was aligned with the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phrase {
+    /// Frames of the phrase after it was moved.
+    pub frames: Range<usize>,
+    /// Frames the phrase was moved later by, or earlier when negative.
+    pub lag: isize,
+    /// Semitones the phrase was shifted by on top of the key shift of the whole target.
+    pub key_shift: f64,
+}
+
+/// Splits the target into the ranges of frames between rests of at least `min_rest` frames.
+fn find_phrases(target: &NoteSeries, min_rest: usize) -> Vec<Range<usize>> {
+    let mut phrases = Vec::new();
+    let mut current = None::<Range<usize>>;
+    for (i, sample) in target.iter().enumerate() {
+        if sample.is_none() {
+            continue;
+        }
+        match &mut current {
+            Some(phrase) if i - phrase.end < min_rest => phrase.end = i + 1,
+            _ => phrases.extend(current.replace(i..i + 1)),
+        }
+    }
+    phrases.extend(current);
+    phrases
+}
+
+/// Moves every phrase of the target by up to `search` frames to where it best lines up with the
+/// input, by the correlation of their [alignment features](alignment_feature), and then shifts
+/// its key to the key it was sung in.
+///
+/// A phrase is only moved within the rests around it, so the phrases keep their order and never
+/// overlap.
+///
+/// Returns the phrases and the frames that every frame of the moved target was moved by.
+pub fn align_phrases(
+    target: &mut NoteSeries,
+    input: &NoteSeries,
+    min_rest: usize,
+    search: usize,
+) -> (Vec<Phrase>, Box<[isize]>) {
+    assert_eq!(
+        target.len(),
+        input.len(),
+        "Signals must have the same length"
+    );
+    let n = target.len();
+    let mut lags = vec![0; n].into_boxed_slice();
+    let (Some(target_feature), Some(input_feature)) =
+        (alignment_feature(target, n), alignment_feature(input, n))
+    else {
+        return (Vec::new(), lags);
+    };
+
+    let original = target.to_vec();
+    target.fill(None);
+    let search = usize_to_isize(search);
+    let ranges = find_phrases(&original, min_rest);
+    let mut phrases = Vec::new();
+    // first frame that the next phrase may be moved to, after the end of the previous one
+    let mut earliest = 0;
+    for (k, frames) in ranges.iter().enumerate() {
+        let score = |lag: isize| {
+            frames
+                .clone()
+                .filter_map(|j| {
+                    let i = usize::try_from(usize_to_isize(j) + lag).ok()?;
+                    Some((input_feature.get(i)?.conj() * target_feature[j]).re)
+                })
+                .sum::<f64>()
+        };
+        // the phrase stays in the rests around it, so that it is not moved onto its neighbours
+        let latest = ranges.get(k + 1).map_or(n, |next| next.start);
+        let lags_allowed = usize_to_isize(earliest) - usize_to_isize(frames.start)
+            ..=usize_to_isize(latest) - usize_to_isize(frames.end);
+        // the lags nearest to the shifted target come first, so they are kept on a tie
+        let (lag, _) = (0..=search)
+            .flat_map(|lag| [lag, -lag])
+            .filter(|lag| lags_allowed.contains(lag))
+            .map(|lag| (lag, score(lag)))
+            .fold((0, f64::NEG_INFINITY), |best, (lag, score)| {
+                if score > best.1 { (lag, score) } else { best }
+            });
+
+        let moved = |j: usize| usize::try_from(usize_to_isize(j) + lag).unwrap_or_default();
+        let (start, end) = (moved(frames.start), moved(frames.end));
+        for (i, j) in (start..end).zip(frames.clone()) {
+            target[i].clone_from(&original[j]);
+            lags[i] = lag;
+        }
+        let key_shift = compute_note_shift(&target[start..end], &input[start..end])
+            .map_or(0., |key_shift| key_shift.semitones);
+        apply_note_shift(&mut target[start..end], key_shift);
+        phrases.push(Phrase {
+            frames: start..end,
+            lag,
+            key_shift,
+        });
+        earliest = end;
+    }
+    (phrases, lags)
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{
+        KeyShift, Phrase, StepPattern, TempoOptions, align_phrases, apply_warp, compute_note_shift,
+        compute_tempo_ratio, compute_time_shift, compute_warp,
+    };
+    use crate::core::Note;
+
+    /// A note series with a note for every positive note number and a rest for every other.
+    fn series(values: &[f64]) -> Box<[Option<Note>]> {
+        values
+            .iter()
+            .map(|&n| (n > 0.).then(|| Note::new(n)))
+            .collect()
+    }
+
+    #[rstest]
+    #[case(&[60., 62., 0., 0., 0., 0., 0., 0.], &[0., 0., 0., 0., 0., 0., 60., 62.], -6.)]
+    #[case(&[0., 0., 0., 0., 0., 0., 60., 62.], &[60., 62., 0., 0., 0., 0., 0., 0.], 6.)]
+    #[case(&[0., 60., 62., 0.], &[0., 60., 62., 0.], 0.)]
+    // sung a fourth higher and half a phrase late
+    #[case(
+        &[60., 60., 67., 67., 65., 65., 64., 64., 62., 62., 60., 60.],
+        &[0., 0., 0., 0., 0., 0., 65., 65., 72., 72., 70., 70.],
+        -6.
+    )]
+    #[case(&[60., 64., 67., 72., 67., 64., 60., 0.], &[67., 72., 67., 64., 60., 0., 0., 0.], 2.)]
+    // held for a frame longer, so the best match is half a frame late
+    #[case(&[0., 60., 60., 0., 0., 0.], &[0., 0., 60., 60., 60., 0.], -1.5)]
+    fn time_shift(#[case] target: &[f64], #[case] input: &[f64], #[case] expected: f64) {
+        let shift = compute_time_shift(&series(target), &series(input)).expect("empty series");
+        assert!((shift - expected).abs() < 0.05);
+    }
+
+    #[rstest]
+    #[case(StepPattern::Symmetric1)]
+    #[case(StepPattern::Symmetric2)]
+    #[case(StepPattern::Asymmetric)]
+    fn warp(#[case] step_pattern: StepPattern) {
+        // the singer rushes the first note and drags the second
+        let mut target = series(&[60., 60., 62., 62., 64., 64., 64., 0.]);
+        let input = series(&[60., 62., 62., 62., 62., 64., 64., 0.]);
+        let path = compute_warp(&target, &input, 2, step_pattern);
+        apply_warp(&mut target, &path);
+        assert_eq!(target, input);
+    }
+
+    #[rstest]
+    #[case(&[62., 64., 66., 67., 69.], Some((2., 1.)))]
+    // the last two frames are sung an octave too high
+    #[case(&[62., 64., 66., 79., 81.], Some((2., 1.)))]
+    // the last two frames are sung on the wrong notes
+    #[case(&[62., 64., 66., 70., 66.], Some((2., 0.6)))]
+    #[case(&[0., 0., 0., 0., 0.], None)]
+    fn note_shift(#[case] input: &[f64], #[case] expected: Option<(f64, f64)>) {
+        let target = series(&[60., 62., 64., 65., 67.]);
+        let shift = compute_note_shift(&target, &series(input));
+        let round = |x: f64| (x * 1e6).round() / 1e6;
+        assert_eq!(
+            shift.map(|s| KeyShift {
+                semitones: round(s.semitones),
+                confidence: round(s.confidence),
+            }),
+            expected.map(|(semitones, confidence)| KeyShift {
+                semitones,
+                confidence,
+            })
+        );
+    }
+
+    #[test]
+    fn phrases() {
+        // the second phrase is sung two frames late and a semitone flat
+        let mut target = series(&[60., 62., 0., 0., 0., 64., 65., 0., 0., 0., 0., 0.]);
+        let input = series(&[60., 62., 0., 0., 0., 0., 0., 63., 64., 0., 0., 0.]);
+        let (phrases, lags) = align_phrases(&mut target, &input, 2, 3);
+        let phrases = phrases
+            .into_iter()
+            .map(|p| Phrase {
+                key_shift: (p.key_shift * 1e6).round() / 1e6,
+                ..p
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phrases,
+            [
+                Phrase {
+                    frames: 0..2,
+                    lag: 0,
+                    key_shift: 0.,
+                },
+                Phrase {
+                    frames: 7..9,
+                    lag: 2,
+                    key_shift: -1.,
+                },
+            ]
+        );
+        assert_eq!(*lags, [0, 0, 0, 0, 0, 0, 0, 2, 2, 0, 0, 0]);
+        assert_eq!(target, input);
+    }
+
+    #[test]
+    fn phrases_overlap() {
+        // both phrases line up best with the only sung frames, but may not be moved onto each other
+        let mut target = series(&[60., 62., 0., 0., 60., 62., 0., 0.]);
+        let input = series(&[0., 0., 60., 62., 0., 0., 0., 0.]);
+        let (phrases, lags) = align_phrases(&mut target, &input, 2, 3);
+        let frames = phrases.iter().map(|p| p.frames.clone()).collect::<Vec<_>>();
+        assert_eq!(frames, [2..4, 4..6]);
+        assert_eq!(*lags, [0, 0, 2, 2, 0, 0, 0, 0]);
+        assert_eq!(*target, *series(&[0., 0., 60., 62., 60., 62., 0., 0.]));
+    }
+
+    #[test]
+    fn tempo_ratio() {
+        let dt = 0.01_f64;
+        let melody = [
+            (0., 60.),
+            (0.3, 62.),
+            (0.5, 0.),
+            (0.7, 64.),
+            (1.2, 65.),
+            (1.4, 67.),
+            (1.6, 0.),
+        ];
+        // the melody at `ratio` times its tempo, starting `-shift` seconds in
+        let sample = |ratio: f64, shift: f64| {
+            (0..300)
+                .map(|i| {
+                    let t = ratio * dt.mul_add(f64::from(i), shift);
+                    melody
+                        .iter()
+                        .rfind(|(start, _)| *start <= t)
+                        .and_then(|&(_, n)| (n > 0.).then(|| Note::new(n)))
+                })
+                .collect::<Vec<_>>()
+        };
+        // sung at 95% of the tempo, starting 0.2 seconds in
+        let input = sample(0.95, -0.2);
+        let (ratio, shift) =
+            compute_tempo_ratio(|ratio| sample(ratio, 0.), &input, &TempoOptions::default())
+                .expect("empty series");
+        assert!((ratio - 0.95).abs() < 0.005, "{ratio}");
+        assert!((shift + 20.).abs() < 1., "{shift}");
+
+        for (min_ratio, max_ratio) in [(0., 1.1), (1.1, 0.9), (0.9, f64::INFINITY)] {
+            let options = TempoOptions {
+                min_ratio,
+                max_ratio,
+            };
+            assert!(compute_tempo_ratio(|ratio| sample(ratio, 0.), &input, &options).is_none());
         }
     }
 }
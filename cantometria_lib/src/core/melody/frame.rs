@@ -0,0 +1,127 @@
+use std::f64::consts::TAU;
+
+use crate::core::usize_to_f64;
+
+/// Taper applied to every analysis frame before pitch estimation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WindowFunction {
+    #[default]
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl WindowFunction {
+    pub(crate) fn coefficients(self, len: usize) -> Box<[f64]> {
+        let denominator = usize_to_f64(len.saturating_sub(1).max(1));
+        (0..len)
+            .map(|n| {
+                let phase = TAU * usize_to_f64(n) / denominator;
+                match self {
+                    Self::Rectangular => 1.,
+                    Self::Hann => 0.5f64.mul_add(-phase.cos(), 0.5),
+                    Self::Hamming => 0.46f64.mul_add(-phase.cos(), 0.54),
+                    Self::Blackman => {
+                        0.08f64.mul_add((2. * phase).cos(), 0.5f64.mul_add(-phase.cos(), 0.42))
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Splits `signal` into overlapping frames of `window` samples every `hop` samples.
+///
+/// Frame `i` is centred on sample `i * hop`, with zeros standing in for samples beyond either end
+/// of the signal, so that the frame times line up with a uniform series of interval `hop`.
+pub fn frames(
+    signal: &[f64],
+    window: usize,
+    hop: usize,
+    window_function: WindowFunction,
+) -> impl Iterator<Item = Vec<f64>> {
+    let coefficients = window_function.coefficients(window);
+    let half = window / 2;
+    (0..signal.len().div_ceil(hop)).map(move |i| {
+        let start = i * hop;
+        coefficients
+            .iter()
+            .enumerate()
+            .map(|(n, c)| {
+                (start + n)
+                    .checked_sub(half)
+                    .and_then(|m| signal.get(m))
+                    .map_or(0., |s| s * c)
+            })
+            .collect()
+    })
+}
+
+/// Root mean square level of each frame produced by [`frames`] with the same sizes, before any
+/// window function is applied.
+pub fn rms(signal: &[f64], window: usize, hop: usize) -> impl Iterator<Item = f64> {
+    let mut energy = Vec::with_capacity(signal.len() + 1);
+    energy.push(0.);
+    for s in signal {
+        energy.push(energy[energy.len() - 1] + s * s);
+    }
+    let half = window / 2;
+    (0..signal.len().div_ceil(hop)).map(move |i| {
+        let start = (i * hop).saturating_sub(half).min(signal.len());
+        let end = (i * hop + window - half).min(signal.len());
+        ((energy[end] - energy[start]) / usize_to_f64(window)).sqrt()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{WindowFunction, frames, rms};
+
+    #[rstest]
+    #[case(WindowFunction::Hann)]
+    #[case(WindowFunction::Hamming)]
+    #[case(WindowFunction::Blackman)]
+    fn symmetric_window(#[case] window_function: WindowFunction) {
+        let coefficients = window_function.coefficients(8);
+        assert!(
+            coefficients
+                .iter()
+                .zip(coefficients.iter().rev())
+                .all(|(a, b)| (a - b).abs() < 1e-12)
+        );
+        assert!(coefficients.iter().all(|c| (-1e-12..=1.).contains(c)));
+    }
+
+    #[test]
+    fn centred_frames() {
+        let signal = [1., 2., 3., 4., 5.];
+        let framed = frames(&signal, 4, 2, WindowFunction::Rectangular).collect::<Vec<_>>();
+        assert_eq!(
+            framed,
+            [
+                vec![0., 0., 1., 2.],
+                vec![1., 2., 3., 4.],
+                vec![3., 4., 5., 0.]
+            ]
+        );
+    }
+
+    #[test]
+    fn rms_levels() {
+        // a full-scale square wave, then silence
+        let signal = [1., -1., 1., -1., 0., 0., 0., 0.];
+        let levels = rms(&signal, 4, 4).collect::<Vec<_>>();
+        // the frames are centred on 0 and 4, so the first is half empty and the second half full
+        let expected = [0.5f64.sqrt(), 0.5f64.sqrt()];
+        assert_eq!(levels.len(), expected.len());
+        assert!(
+            levels
+                .iter()
+                .zip(expected)
+                .all(|(l, e)| (l - e).abs() < 1e-12)
+        );
+    }
+}
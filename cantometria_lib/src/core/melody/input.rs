@@ -1,20 +1,19 @@
-use hound::WavReader;
-use pitch_detection::detector::PitchDetector;
-use pitch_detection::detector::yin::YINDetector;
-use std::{fs::File, io::BufReader, path::Path};
+use hound::{SampleFormat, WavReader};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
 
 use crate::{
-    core::{DynNoteTimeSeries, model::Note, usize_to_f64},
+    core::{
+        Detection, DynNoteTimeSeries, PitchEstimator, Tuning, f64_to_usize, model::Note,
+        usize_to_f64,
+    },
     error::NewUnpaddedInputMelodyError,
 };
 
-fn frequency_to_note_number(frequency: f64) -> Option<f64> {
-    const A4_FREQUENCY: f64 = 440.0;
-    (frequency > 0.0).then(|| {
-        let semitones_from_a4 = 12.0 * (frequency / A4_FREQUENCY).log2();
-        69.0 + semitones_from_a4
-    })
-}
+use super::frame::{WindowFunction, frames, rms};
 
 type WavFile = WavReader<BufReader<File>>;
 
@@ -22,50 +21,319 @@ pub fn open_wav<P: AsRef<Path>>(path: P) -> Result<WavFile, hound::Error> {
     WavReader::open(path)
 }
 
+/// Decodes every sample of the WAV file into the range `[-1.0, 1.0]`.
+///
+/// Integer samples of any bit depth up to 32 are scaled by their full-scale value, while
+/// 32-bit float samples are taken as is. Samples remain interleaved by channel.
+fn decode_samples<R: Read>(
+    wav: &mut WavReader<R>,
+) -> Result<Vec<f64>, NewUnpaddedInputMelodyError> {
+    let spec = wav.spec();
+    match (spec.sample_format, spec.bits_per_sample) {
+        (SampleFormat::Int, bits @ 1..=32) => {
+            let full_scale = f64::from(1u32 << (bits - 1));
+            wav.samples::<i32>()
+                .map(|s| Ok(f64::from(s?) / full_scale))
+                .collect()
+        }
+        (SampleFormat::Float, 32) => wav.samples::<f32>().map(|s| Ok(f64::from(s?))).collect(),
+        (format, bits) => Err(NewUnpaddedInputMelodyError::UnsupportedSampleFormat(
+            format, bits,
+        )),
+    }
+}
+
+/// Selects how the channels of a multichannel recording become the single signal analysed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelMode {
+    /// Average of every channel in each frame.
+    #[default]
+    Mix,
+    /// First channel only.
+    Left,
+    /// Second channel only.
+    Right,
+    /// The channel at the given zero-based index only.
+    Index(u16),
+    /// The channel with the highest RMS over the whole recording.
+    Loudest,
+}
+
+impl ChannelMode {
+    /// De-interleaves `samples` into a single channel according to the mode.
+    fn downmix(
+        self,
+        samples: &[f64],
+        channels: u16,
+    ) -> Result<Vec<f64>, NewUnpaddedInputMelodyError> {
+        let frames = samples.chunks_exact(channels.into());
+        let channel = match self {
+            Self::Mix => {
+                let scale = f64::from(channels);
+                return Ok(frames
+                    .map(|frame| frame.iter().sum::<f64>() / scale)
+                    .collect());
+            }
+            Self::Left => 0,
+            Self::Right => 1,
+            Self::Index(channel) => channel,
+            Self::Loudest => {
+                // energy of every channel, summed in one pass over the frames
+                let mut energies = vec![0.; channels.into()];
+                for frame in frames.clone() {
+                    for (energy, sample) in energies.iter_mut().zip(frame) {
+                        *energy += sample.powi(2);
+                    }
+                }
+                (0..channels)
+                    .zip(energies)
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map_or(0, |(channel, _)| channel)
+            }
+        };
+        if channel >= channels {
+            return Err(NewUnpaddedInputMelodyError::ChannelOutOfRange { channel, channels });
+        }
+        Ok(frames.map(|frame| frame[usize::from(channel)]).collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputOptions {
+    pub channel_mode: ChannelMode,
+    /// Length of each analysis frame in seconds, rounded to the nearest sample and at least two.
+    pub window_secs: f64,
+    /// Time between the centres of consecutive analysis frames in seconds.
+    pub hop_secs: f64,
+    pub window_function: WindowFunction,
+}
+
+impl Default for InputOptions {
+    fn default() -> Self {
+        Self {
+            channel_mode: ChannelMode::default(),
+            window_secs: 0.023,
+            hop_secs: 0.005_8,
+            window_function: WindowFunction::default(),
+        }
+    }
+}
+
+impl InputOptions {
+    /// Converts the window and hop durations to sample counts at `sample_rate`.
+    ///
+    /// # Errors
+    /// Either duration is not a positive number of seconds.
+    fn frame_sizes(
+        &self,
+        sample_rate: usize,
+    ) -> Result<(usize, usize), NewUnpaddedInputMelodyError> {
+        let valid = |secs: f64| secs.is_finite() && secs > 0.;
+        if !valid(self.window_secs) || !valid(self.hop_secs) {
+            return Err(NewUnpaddedInputMelodyError::InvalidFrameDuration {
+                window_secs: self.window_secs,
+                hop_secs: self.hop_secs,
+            });
+        }
+        let sample_rate = usize_to_f64(sample_rate);
+        let window = f64_to_usize((self.window_secs * sample_rate).round()).max(2);
+        let hop = f64_to_usize((self.hop_secs * sample_rate).round()).max(1);
+        Ok((window, hop))
+    }
+}
+
 pub struct UnpaddedInputMelody {
     pub notes: DynNoteTimeSeries,
 }
 
 impl UnpaddedInputMelody {
-    pub fn new(mut wav: WavFile) -> Result<Self, NewUnpaddedInputMelodyError> {
-        const SIZE: usize = 1024;
-        const PADDING: usize = SIZE / 2;
-        const POWER_THRESHOLD: f64 = 1.0;
-        const CLARITY_THRESHOLD: f64 = 0.9;
-
+    pub fn new<R: Read, E: PitchEstimator>(
+        mut wav: WavReader<R>,
+        options: &InputOptions,
+        tuning: &Tuning,
+        mut estimator: E,
+    ) -> Result<Self, NewUnpaddedInputMelodyError> {
         let spec = wav.spec();
         let sample_rate = spec.sample_rate as usize;
-        let mut detector = YINDetector::new(SIZE, PADDING);
-        let mut samples_f64: Vec<f64> = Vec::with_capacity(SIZE);
-        let mut notes = Vec::new();
-
-        let num_channels = f64::from(spec.channels);
-        let chunk_duration_seconds =
-            (usize_to_f64(SIZE) / num_channels) / usize_to_f64(sample_rate);
-
-        wav.samples::<i16>().try_for_each(|s| {
-            let sample = f64::from(s?) / f64::from(i16::MAX);
-            samples_f64.push(sample);
-
-            if samples_f64.len() >= SIZE {
-                let pitch_result = detector.get_pitch(
-                    &samples_f64,
-                    sample_rate,
-                    POWER_THRESHOLD,
-                    CLARITY_THRESHOLD,
-                );
-
-                notes.push(
-                    pitch_result
-                        .and_then(|p| Some(Note::new(frequency_to_note_number(p.frequency)?))),
-                );
-                samples_f64.clear();
-            }
-            Ok::<_, NewUnpaddedInputMelodyError>(())
-        })?;
+        let (window, hop) = options.frame_sizes(sample_rate)?;
+        let hop_duration_seconds = usize_to_f64(hop) / usize_to_f64(sample_rate);
+
+        let samples = decode_samples(&mut wav)?;
+        let signal = options.channel_mode.downmix(&samples, spec.channels)?;
+        let notes = estimator
+            .track(
+                frames(&signal, window, hop, options.window_function),
+                sample_rate,
+            )
+            .into_iter()
+            .zip(rms(&signal, window, hop))
+            .map(|(pitch, rms)| {
+                let pitch = pitch?;
+                let detection = Detection {
+                    frequency: pitch.frequency,
+                    clarity: pitch.clarity,
+                    rms,
+                };
+                Some(Note::detected(
+                    tuning.frequency_to_note_number(pitch.frequency)?,
+                    detection,
+                ))
+            })
+            .collect();
 
         Ok(Self {
-            notes: DynNoteTimeSeries::new(notes, chunk_duration_seconds.into()),
+            notes: DynNoteTimeSeries::new(notes, hop_duration_seconds.into()),
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+    use rstest::rstest;
+
+    use super::{ChannelMode, InputOptions, UnpaddedInputMelody, decode_samples};
+    use crate::core::{PYin, Tuning, Yin};
+
+    const SAMPLE_RATE: u32 = 44_100;
+    const FREQUENCY: f64 = 440.;
+    const AMPLITUDE: f64 = 0.5;
+
+    fn sine(n: usize) -> f64 {
+        AMPLITUDE
+            * (std::f64::consts::TAU * FREQUENCY * crate::core::usize_to_f64(n)
+                / f64::from(SAMPLE_RATE))
+            .sin()
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_sine(sample_format: SampleFormat, bits_per_sample: u16) -> Vec<u8> {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample,
+            sample_format,
+        };
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut buffer, spec).expect("creating writer failed");
+        let full_scale = f64::from(1u32 << (bits_per_sample - 1));
+        for n in 0..SAMPLE_RATE as usize {
+            match sample_format {
+                SampleFormat::Int => writer.write_sample((sine(n) * full_scale).round() as i32),
+                SampleFormat::Float => writer.write_sample(sine(n) as f32),
+            }
+            .expect("writing sample failed");
+        }
+        writer.finalize().expect("finalising writer failed");
+        buffer.into_inner()
+    }
+
+    #[rstest]
+    #[case(SampleFormat::Int, 8, 1e-2)]
+    #[case(SampleFormat::Int, 16, 1e-4)]
+    #[case(SampleFormat::Int, 24, 1e-6)]
+    #[case(SampleFormat::Int, 32, 1e-8)]
+    #[case(SampleFormat::Float, 32, 1e-6)]
+    fn decode(
+        #[case] sample_format: SampleFormat,
+        #[case] bits_per_sample: u16,
+        #[case] tolerance: f64,
+    ) {
+        let bytes = write_sine(sample_format, bits_per_sample);
+
+        let mut wav = WavReader::new(Cursor::new(&bytes)).expect("reading wav failed");
+        let samples = decode_samples(&mut wav).expect("decoding failed");
+        assert_eq!(samples.len(), SAMPLE_RATE as usize);
+        assert!(
+            samples
+                .iter()
+                .enumerate()
+                .all(|(n, s)| (s - sine(n)).abs() < tolerance)
+        );
+
+        let wav = WavReader::new(Cursor::new(&bytes)).expect("reading wav failed");
+        let melody = UnpaddedInputMelody::new(
+            wav,
+            &InputOptions::default(),
+            &Tuning::default(),
+            Yin::default(),
+        )
+        .expect("analysis failed");
+        let voiced = melody.notes.samples().iter().flatten().collect::<Box<_>>();
+        assert!(voiced.len() * 10 >= melody.notes.len() * 9);
+        assert!(
+            voiced
+                .iter()
+                .all(|note| (note.note_number - 69.).abs() < 0.1)
+        );
+    }
+
+    #[rstest]
+    #[case(ChannelMode::Mix, &[0.5, -0.5])]
+    #[case(ChannelMode::Left, &[1., -1.])]
+    #[case(ChannelMode::Right, &[0., 0.])]
+    #[case(ChannelMode::Index(2), &[0.5, -0.5])]
+    #[case(ChannelMode::Loudest, &[1., -1.])]
+    fn downmix(#[case] channel_mode: ChannelMode, #[case] expected: &[f64]) {
+        let samples = [1., 0., 0.5, -1., 0., -0.5];
+        let mixed = channel_mode
+            .downmix(&samples, 3)
+            .expect("downmixing failed");
+        assert_eq!(mixed, expected);
+    }
+
+    #[test]
+    fn tiny_window() {
+        let bytes = write_sine(SampleFormat::Int, 16);
+        let wav = WavReader::new(Cursor::new(&bytes)).expect("reading wav failed");
+        let options = InputOptions {
+            window_secs: 1e-5,
+            ..InputOptions::default()
+        };
+        let mut estimator = PYin::default();
+        estimator.power_threshold = 0.;
+        let melody = UnpaddedInputMelody::new(wav, &options, &Tuning::default(), estimator)
+            .expect("analysis failed");
+        assert!(melody.notes.samples().iter().all(Option::is_none));
+    }
+
+    #[rstest]
+    #[case(0.023, (1014, 256))]
+    #[case(0.035, (1544, 256))]
+    #[case(1e-9, (2, 256))]
+    fn frame_sizes(#[case] window_secs: f64, #[case] expected: (usize, usize)) {
+        let options = InputOptions {
+            window_secs,
+            hop_secs: 256. / f64::from(SAMPLE_RATE),
+            ..InputOptions::default()
+        };
+        let sizes = options
+            .frame_sizes(SAMPLE_RATE as usize)
+            .expect("converting frame sizes failed");
+        assert_eq!(sizes, expected);
+    }
+
+    #[rstest]
+    #[case(f64::NAN, 0.01)]
+    #[case(0., 0.01)]
+    #[case(-0.02, 0.01)]
+    #[case(f64::INFINITY, 0.01)]
+    #[case(0.02, f64::NAN)]
+    #[case(0.02, 0.)]
+    #[case(0.02, -0.01)]
+    fn invalid_frame_sizes(#[case] window_secs: f64, #[case] hop_secs: f64) {
+        let options = InputOptions {
+            window_secs,
+            hop_secs,
+            ..InputOptions::default()
+        };
+        assert!(options.frame_sizes(SAMPLE_RATE as usize).is_err());
+    }
+
+    #[test]
+    fn downmix_out_of_range() {
+        assert!(ChannelMode::Right.downmix(&[0., 1.], 1).is_err());
+    }
+}
@@ -1,5 +1,17 @@
+mod frame;
 mod input;
+mod musicxml;
+mod reference;
 mod target;
+mod tempo;
+mod transcribe;
+mod ultrastar;
 
-pub use input::{UnpaddedInputMelody, open_wav};
-pub use target::{RawUnpaddedTargetMelody, open_midi};
+pub use frame::WindowFunction;
+pub use input::{ChannelMode, InputOptions, UnpaddedInputMelody, open_wav};
+pub use reference::SegmentOptions;
+pub use target::{
+    Midi, Polyphony, RawUnpaddedTargetMelody, TargetOptions, TrackSelection, open_midi, open_target,
+};
+pub use tempo::{BarBeat, Position, TempoMap, TimeDivision};
+pub use transcribe::TranscribeOptions;
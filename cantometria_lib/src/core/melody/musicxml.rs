@@ -0,0 +1,642 @@
+use std::{
+    io::{BufReader, Read, Seek},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+use ordered_float::OrderedFloat;
+use roxmltree::{Document, Node, ParsingOptions};
+use zip::ZipArchive;
+
+use crate::{
+    core::{Lyric, Note, Tuning},
+    error::{OpenTargetError, ParseMusicXmlError},
+};
+
+use super::{
+    RawUnpaddedTargetMelody, TargetOptions, TrackSelection,
+    target::NoteReducer,
+    tempo::{TempoMap, TimeDivision},
+};
+
+/// Voice of notes that do not name one.
+const DEFAULT_VOICE: &str = "1";
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|c| c.text()).map(str::trim)
+}
+
+fn parse_value<T: FromStr>(name: &str, text: &str) -> Result<T, ParseMusicXmlError> {
+    text.trim()
+        .parse()
+        .map_err(|_| ParseMusicXmlError::InvalidValue(name.to_owned(), text.to_owned()))
+}
+
+/// Parses the text of the child element `name` of `node`, if present.
+fn value<T: FromStr>(node: Node, name: &str) -> Result<Option<T>, ParseMusicXmlError> {
+    child_text(node, name)
+        .map(|text| parse_value(name, text))
+        .transpose()
+}
+
+/// Semitones above C of a pitch step.
+fn step_semitones(step: &str) -> Result<f64, ParseMusicXmlError> {
+    Ok(match step {
+        "C" => 0.,
+        "D" => 2.,
+        "E" => 4.,
+        "F" => 5.,
+        "G" => 7.,
+        "A" => 9.,
+        "B" => 11.,
+        _ => {
+            return Err(ParseMusicXmlError::InvalidValue(
+                "step".to_owned(),
+                step.to_owned(),
+            ));
+        }
+    })
+}
+
+/// Length in quarter notes of a note type of a metronome marking.
+fn beat_unit_quarters(unit: &str) -> Option<f64> {
+    Some(match unit {
+        "breve" => 8.,
+        "whole" => 4.,
+        "half" => 2.,
+        "quarter" => 1.,
+        "eighth" => 0.5,
+        "16th" => 0.25,
+        "32nd" => 0.125,
+        _ => return None,
+    })
+}
+
+/// Quarter notes per minute of a metronome marking, which may be text such as "c. 60" that is not
+/// read.
+fn metronome_tempo(metronome: Node) -> Option<f64> {
+    let unit = beat_unit_quarters(child_text(metronome, "beat-unit")?)?;
+    let dots = metronome
+        .children()
+        .filter(|c| c.has_tag_name("beat-unit-dot"))
+        .count();
+    let unit = (0..dots)
+        .fold((unit, unit), |(length, dot), _| {
+            (length + dot / 2., dot / 2.)
+        })
+        .0;
+    let per_minute = child_text(metronome, "per-minute")?.parse::<f64>().ok()?;
+    Some(per_minute * unit)
+}
+
+/// A pitched note of a part, timed in quarter notes from the start of the score.
+struct ScoreNote {
+    start: f64,
+    end: f64,
+    /// MIDI key of the nearest equal tempered pitch.
+    key: u8,
+    /// Semitones from the key to the written pitch, for microtonal alterations.
+    detune: f64,
+    voice: String,
+    lyric: Option<Lyric>,
+    /// Whether a tie continues the note into a following one.
+    tied: bool,
+}
+
+/// The notes of a part in the selected voice and the tempo and time signature changes marked in
+/// the part.
+struct Part<'a> {
+    id: &'a str,
+    notes: Vec<ScoreNote>,
+    /// Quarter notes from the start of the score and seconds per quarter note of each change.
+    tempos: Vec<(f64, f64)>,
+    /// Quarter notes from the start of the score, beats per bar and quarter notes per beat of each
+    /// change.
+    meters: Vec<(f64, f64, f64)>,
+}
+
+/// Reads the first lyric of a note, starting a word with a space unless it is the first word.
+fn read_lyric(note: Node, first: bool, line: usize) -> Option<(Lyric, bool)> {
+    let lyric = child(note, "lyric")?;
+    let text = lyric
+        .children()
+        .filter(|c| c.has_tag_name("text"))
+        .filter_map(|c| c.text())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let starts_word = matches!(
+        child_text(lyric, "syllabic"),
+        None | Some("single" | "begin")
+    );
+    let syllable = if starts_word && !first {
+        format!(" {text}")
+    } else {
+        text
+    };
+    let end_line = lyric
+        .children()
+        .any(|c| c.has_tag_name("end-line") || c.has_tag_name("end-paragraph"));
+    Some((Lyric { syllable, line }, end_line))
+}
+
+/// Quarter notes per minute of the tempo set by a sound or direction element, if any.
+fn read_tempo(element: Node) -> Result<Option<f64>, ParseMusicXmlError> {
+    let sound = if element.has_tag_name("sound") {
+        Some(element)
+    } else {
+        child(element, "sound")
+    };
+    let tempo = sound
+        .and_then(|s| s.attribute("tempo"))
+        .map(|t| parse_value::<f64>("tempo", t))
+        .transpose()?
+        .or_else(|| {
+            element
+                .descendants()
+                .find(|d| d.has_tag_name("metronome"))
+                .and_then(metronome_tempo)
+        });
+    Ok(tempo.filter(|&t| t > 0.))
+}
+
+/// Beats per bar and quarter notes per beat of a time signature, unless it is composite, such as
+/// 3+2 beats, or has no beats.
+fn read_time(time: Node) -> Option<(f64, f64)> {
+    let beats = child_text(time, "beats")?.parse::<f64>().ok()?;
+    let beat_type = child_text(time, "beat-type")?.parse::<f64>().ok()?;
+    (beats > 0. && beat_type > 0.).then(|| (beats, 4. / beat_type))
+}
+
+/// MIDI key of the nearest equal tempered pitch to a written pitch, and the semitones from it to
+/// the written pitch.
+fn read_pitch(pitch: Node) -> Result<(u8, f64), ParseMusicXmlError> {
+    let step = step_semitones(child_text(pitch, "step").unwrap_or_default())?;
+    let alter = value::<f64>(pitch, "alter")?.unwrap_or_default();
+    let octave = value::<i32>(pitch, "octave")?.unwrap_or(4);
+    let written = f64::from((octave + 1) * 12) + step + alter;
+    #[allow(clippy::cast_possible_truncation)]
+    let rounded = written.round() as i32;
+    let key = u8::try_from(rounded)
+        .ok()
+        .filter(|&key| key < 128)
+        .ok_or(ParseMusicXmlError::PitchOutOfRange(rounded))?;
+    Ok((key, written - f64::from(key)))
+}
+
+impl<'a> Part<'a> {
+    /// Reads the notes of `voice`, or of every voice if `None`, from the measures of a part.
+    fn read(part: Node<'a, '_>, voice: Option<&str>) -> Result<Self, ParseMusicXmlError> {
+        let mut notes = Vec::<ScoreNote>::new();
+        let mut tempos = Vec::new();
+        let mut meters = Vec::new();
+        let mut divisions = 1.;
+        let mut position = 0.;
+        // onset of the previous note, shared by the notes of a chord
+        let mut onset = 0.;
+        let mut line = 0;
+        let mut line_has_lyrics = false;
+
+        for measure in part.children().filter(|c| c.has_tag_name("measure")) {
+            for element in measure.children().filter(Node::is_element) {
+                let duration = || -> Result<f64, ParseMusicXmlError> {
+                    Ok(value::<f64>(element, "duration")?.unwrap_or_default() / divisions)
+                };
+                match element.tag_name().name() {
+                    "attributes" => {
+                        divisions = value(element, "divisions")?.unwrap_or(divisions);
+                        let time = child(element, "time").and_then(read_time);
+                        meters.extend(time.map(|(beats, per_beat)| (position, beats, per_beat)));
+                    }
+                    "print" => {
+                        let breaks = ["new-system", "new-page"]
+                            .iter()
+                            .any(|name| element.attribute(*name) == Some("yes"));
+                        if breaks && line_has_lyrics {
+                            line += 1;
+                            line_has_lyrics = false;
+                        }
+                    }
+                    "sound" | "direction" => {
+                        if let Some(tempo) = read_tempo(element)? {
+                            tempos.push((position, 60. / tempo));
+                        }
+                    }
+                    "backup" => position -= duration()?,
+                    "forward" => position += duration()?,
+                    "note" => {
+                        // grace and cue notes take no time
+                        if child(element, "grace").is_some() || child(element, "cue").is_some() {
+                            continue;
+                        }
+                        let length = duration()?;
+                        if child(element, "chord").is_none() {
+                            onset = position;
+                            position += length;
+                        }
+                        let Some(pitch) = child(element, "pitch") else {
+                            continue;
+                        };
+                        let note_voice = child_text(element, "voice").unwrap_or(DEFAULT_VOICE);
+                        if voice.is_some_and(|v| v != note_voice) || length <= 0. {
+                            continue;
+                        }
+
+                        let (key, detune) = read_pitch(pitch)?;
+                        let ties = element
+                            .children()
+                            .filter(|c| c.has_tag_name("tie"))
+                            .filter_map(|c| c.attribute("type"))
+                            .collect::<Vec<_>>();
+                        let (tie_start, tie_stop) =
+                            (ties.contains(&"start"), ties.contains(&"stop"));
+
+                        let end = onset + length;
+                        if tie_stop
+                            && let Some(tied) = notes.iter_mut().rev().find(|n| {
+                                n.tied
+                                    && n.key == key
+                                    && n.voice == note_voice
+                                    && OrderedFloat(n.end) == OrderedFloat(onset)
+                            })
+                        {
+                            tied.end = end;
+                            tied.tied = tie_start;
+                            continue;
+                        }
+
+                        let lyric =
+                            read_lyric(element, notes.is_empty(), line).map(|(lyric, end_line)| {
+                                line_has_lyrics = !end_line;
+                                if end_line {
+                                    line += 1;
+                                }
+                                lyric
+                            });
+                        notes.push(ScoreNote {
+                            start: onset,
+                            end,
+                            key,
+                            detune,
+                            voice: note_voice.to_owned(),
+                            lyric,
+                            tied: tie_start,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(Self {
+            id: part.attribute("id").unwrap_or_default(),
+            notes,
+            tempos,
+            meters,
+        })
+    }
+}
+
+/// Converts quarter notes from the start of the score to seconds and bars and beats, following
+/// the tempo and time signature changes of every part, with one tick for each quarter note.
+fn tempo_map(parts: &[Part]) -> TempoMap {
+    TempoMap::from_changes(
+        TimeDivision::TicksPerQuarter(1),
+        parts.iter().flat_map(|part| part.tempos.clone()).collect(),
+        parts.iter().flat_map(|part| part.meters.clone()).collect(),
+    )
+}
+
+/// Name and zero-based MIDI program of each part in the part list of a score.
+fn part_info<'a>(score: Node<'a, '_>, id: &str) -> (Option<&'a str>, Option<u8>) {
+    let Some(score_part) = child(score, "part-list").and_then(|list| {
+        list.children()
+            .find(|c| c.has_tag_name("score-part") && c.attribute("id") == Some(id))
+    }) else {
+        return (None, None);
+    };
+    let program = score_part
+        .descendants()
+        .find(|d| d.has_tag_name("midi-program"))
+        .and_then(|p| p.text()?.trim().parse::<u8>().ok())
+        .and_then(|p| p.checked_sub(1));
+    (child_text(score_part, "part-name"), program)
+}
+
+impl RawUnpaddedTargetMelody {
+    /// Reads the melody of the part and voice chosen by `options` from a partwise `MusicXML`
+    /// score, keeping the syllable of every note.
+    ///
+    /// Tied notes are read as one note and chords are reduced as the options select. Notes are
+    /// timed by the tempo markings of every part, at 120 quarter notes per minute until the first.
+    ///
+    /// # Errors
+    /// - the score is malformed or not partwise
+    /// - no part or several parts match the selection
+    /// - a note is out of the MIDI range or not mapped by the tuning
+    pub fn from_musicxml(
+        score: &str,
+        options: &TargetOptions,
+        tuning: &Tuning,
+    ) -> Result<Self, ParseMusicXmlError> {
+        let document = Document::parse_with_options(
+            score,
+            ParsingOptions {
+                allow_dtd: true,
+                ..ParsingOptions::default()
+            },
+        )?;
+        let score = document.root_element();
+        if !score.has_tag_name("score-partwise") {
+            return Err(ParseMusicXmlError::UnsupportedRoot(
+                score.tag_name().name().to_owned(),
+            ));
+        }
+        let parts = score
+            .children()
+            .filter(|c| c.has_tag_name("part"))
+            .map(|part| Part::read(part, options.voice.as_deref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tempo_map = tempo_map(&parts);
+
+        let part = if let TrackSelection::Index(index) = options.track {
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| parts.get(index))
+                .ok_or(ParseMusicXmlError::PartNotFound)?
+        } else {
+            let matches = parts
+                .iter()
+                .filter(|part| {
+                    let (name, program) = part_info(score, part.id);
+                    !part.notes.is_empty()
+                        && match &options.track {
+                            TrackSelection::Auto | TrackSelection::Index(_) => true,
+                            TrackSelection::Name(n) => name.is_some_and(|name| name == n),
+                            TrackSelection::Program(p) => program == Some(*p),
+                        }
+                })
+                .collect::<Box<_>>();
+            match *matches {
+                [part] => part,
+                [] => return Err(ParseMusicXmlError::PartNotFound),
+                _ => {
+                    return Err(ParseMusicXmlError::PartAmbiguous(
+                        matches.iter().map(|part| part.id.to_owned()).collect(),
+                    ));
+                }
+            }
+        };
+
+        let mut events = Vec::new();
+        for (note, id) in part.notes.iter().zip(0u32..) {
+            events.push((note.start, true, id, note));
+            events.push((note.end, false, id, note));
+        }
+        // notes released before others start at the same time
+        events.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        let mut reducer = NoteReducer::new(options.polyphony);
+        for (quarters, start, id, note) in events {
+            let time = tempo_map.seconds(quarters);
+            if start {
+                let mut target_note = Note::new(
+                    tuning
+                        .note_to_note_number(note.key)
+                        .ok_or(ParseMusicXmlError::UnmappedNote(note.key))?
+                        + note.detune,
+                );
+                target_note.lyric = note.lyric.clone().map(Arc::new);
+                reducer.press(time, id, target_note);
+            } else {
+                reducer.release(time, id);
+            }
+        }
+        Ok(reducer.finish(Some(tempo_map)))
+    }
+}
+
+/// Finds and reads the score of a compressed `MusicXML` file, named by its container file or else
+/// the first `MusicXML` file outside of `META-INF`.
+fn read_mxl<R: Read + Seek>(reader: R) -> Result<String, OpenTargetError> {
+    fn read<R: Read + Seek>(
+        archive: &mut ZipArchive<R>,
+        name: &str,
+    ) -> Result<String, OpenTargetError> {
+        let mut text = String::new();
+        archive.by_name(name)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    let mut archive = ZipArchive::new(reader)?;
+    let root_file = match read(&mut archive, "META-INF/container.xml") {
+        Ok(container) => Document::parse(&container)
+            .map_err(ParseMusicXmlError::from)?
+            .descendants()
+            .find(|d| d.has_tag_name("rootfile"))
+            .and_then(|r| r.attribute("full-path"))
+            .map(str::to_owned),
+        Err(_) => None,
+    };
+    let root_file = root_file
+        .or_else(|| {
+            archive
+                .file_names()
+                .filter(|name| !name.starts_with("META-INF/"))
+                .find(|name| {
+                    Path::new(name).extension().is_some_and(|e| {
+                        e.eq_ignore_ascii_case("xml") || e.eq_ignore_ascii_case("musicxml")
+                    })
+                })
+                .map(str::to_owned)
+        })
+        .ok_or(OpenTargetError::MxlScoreMissing)?;
+    read(&mut archive, &root_file)
+}
+
+/// Reads the melody of the `MusicXML` score at `path`, which is compressed if its extension is
+/// `.mxl`.
+///
+/// # Errors
+/// - opening or decompressing the file failed
+/// - see [`RawUnpaddedTargetMelody::from_musicxml`]
+pub fn open_musicxml<P: AsRef<Path>>(
+    path: P,
+    options: &TargetOptions,
+    tuning: &Tuning,
+) -> Result<RawUnpaddedTargetMelody, OpenTargetError> {
+    let path = path.as_ref();
+    let compressed = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("mxl"));
+    let score = if compressed {
+        read_mxl(BufReader::new(std::fs::File::open(path)?))?
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    Ok(RawUnpaddedTargetMelody::from_musicxml(
+        &score, options, tuning,
+    )?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use rstest::rstest;
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::read_mxl;
+    use crate::core::{Polyphony, RawUnpaddedTargetMelody, TargetOptions, TrackSelection, Tuning};
+
+    /// A vocal part in 3/4 with a rest, a tie across a system break and two voices after a tempo
+    /// change, and a piano part.
+    const SCORE: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">
+<score-partwise version="4.0">
+  <part-list>
+    <score-part id="P1"><part-name>Voice</part-name>
+      <midi-instrument id="P1-I1"><midi-program>53</midi-program></midi-instrument></score-part>
+    <score-part id="P2"><part-name>Piano</part-name></score-part>
+  </part-list>
+  <part id="P1">
+    <measure number="1">
+      <attributes><divisions>2</divisions><time><beats>3</beats><beat-type>4</beat-type></time></attributes>
+      <direction><direction-type><metronome><beat-unit>quarter</beat-unit><per-minute>120</per-minute></metronome></direction-type></direction>
+      <note><pitch><step>C</step><octave>4</octave></pitch><duration>2</duration><voice>1</voice>
+        <lyric><syllabic>single</syllabic><text>Hi</text></lyric></note>
+      <note><rest/><duration>2</duration><voice>1</voice></note>
+      <note><pitch><step>D</step><octave>4</octave></pitch><duration>2</duration><voice>1</voice><tie type="start"/>
+        <lyric><syllabic>begin</syllabic><text>to</text></lyric></note>
+    </measure>
+    <measure number="2">
+      <print new-system="yes"/>
+      <note><pitch><step>D</step><octave>4</octave></pitch><duration>2</duration><voice>1</voice><tie type="stop"/></note>
+      <note><pitch><step>E</step><octave>4</octave></pitch><duration>2</duration><voice>1</voice>
+        <lyric><syllabic>end</syllabic><text>day</text></lyric></note>
+      <direction><sound tempo="60"/></direction>
+      <note><pitch><step>F</step><alter>1</alter><octave>4</octave></pitch><duration>4</duration><voice>1</voice></note>
+      <backup><duration>4</duration></backup>
+      <note><pitch><step>A</step><octave>3</octave></pitch><duration>4</duration><voice>2</voice>
+        <lyric><text>la</text></lyric></note>
+    </measure>
+  </part>
+  <part id="P2">
+    <measure number="1">
+      <attributes><divisions>1</divisions></attributes>
+      <note><pitch><step>C</step><octave>3</octave></pitch><duration>1</duration></note>
+    </measure>
+  </part>
+</score-partwise>
+"#;
+
+    /// Time, note number and lyric of a target note event.
+    type LyricEvent = (f64, Option<(f64, Option<(String, usize)>)>);
+
+    fn events(options: &TargetOptions) -> Vec<LyricEvent> {
+        let melody = RawUnpaddedTargetMelody::from_musicxml(SCORE, options, &Tuning::default())
+            .expect("parsing failed");
+        melody
+            .note_events()
+            .iter()
+            .map(|event| {
+                let note = event.value.as_ref().map(|note| {
+                    let lyric = note.lyric.as_ref().map(|l| (l.syllable.clone(), l.line));
+                    (note.note_number, lyric)
+                });
+                (*event.time, note)
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case(Some("1"), Polyphony::MostRecent, 66., None)]
+    #[case(None, Polyphony::MostRecent, 57., Some((" la".to_owned(), 1)))]
+    #[case(None, Polyphony::Highest, 66., None)]
+    fn parse(
+        #[case] voice: Option<&str>,
+        #[case] polyphony: Polyphony,
+        #[case] last: f64,
+        #[case] last_lyric: Option<(String, usize)>,
+    ) {
+        let options = TargetOptions {
+            track: TrackSelection::Name("Voice".to_owned()),
+            voice: voice.map(str::to_owned),
+            polyphony,
+            ..TargetOptions::default()
+        };
+        assert_eq!(
+            events(&options),
+            [
+                (0., Some((60., Some(("Hi".to_owned(), 0))))),
+                (0.5, None),
+                (1., Some((62., Some((" to".to_owned(), 0))))),
+                (2., Some((64., Some(("day".to_owned(), 1))))),
+                (2.5, Some((last, last_lyric))),
+                (4.5, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let options = TargetOptions {
+            track: TrackSelection::Name("Voice".to_owned()),
+            ..TargetOptions::default()
+        };
+        let melody = RawUnpaddedTargetMelody::from_musicxml(SCORE, &options, &Tuning::default())
+            .expect("parsing failed");
+        // the note after the tie, a beat into the second bar
+        let position = melody.note_position(2).expect("note has no position");
+        assert!((position.seconds - 2.).abs() < 1e-9);
+        assert!((position.ticks - 4.).abs() < 1e-9);
+        assert_eq!(position.bar_beat.to_string(), "2:2.00");
+    }
+
+    #[rstest]
+    #[case(TrackSelection::Index(1), Ok(48.))]
+    #[case(TrackSelection::Program(52), Ok(60.))]
+    #[case(TrackSelection::Auto, Err(()))]
+    #[case(TrackSelection::Name("Drums".to_owned()), Err(()))]
+    fn select(#[case] track: TrackSelection, #[case] first: Result<f64, ()>) {
+        let options = TargetOptions {
+            track,
+            ..TargetOptions::default()
+        };
+        let melody = RawUnpaddedTargetMelody::from_musicxml(SCORE, &options, &Tuning::default());
+        let first_note = melody.map_err(|_| ()).map(|melody| {
+            melody.note_events()[0]
+                .value
+                .as_ref()
+                .map_or(f64::NAN, |note| note.note_number)
+        });
+        assert_eq!(first_note, first);
+    }
+
+    #[test]
+    fn compressed() {
+        let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        archive
+            .start_file("META-INF/container.xml", options)
+            .expect("adding container failed");
+        archive
+            .write_all(
+                br#"<container><rootfiles><rootfile full-path="song/score.musicxml"/></rootfiles></container>"#,
+            )
+            .expect("writing container failed");
+        archive
+            .start_file("song/score.musicxml", options)
+            .expect("adding score failed");
+        archive
+            .write_all(SCORE.as_bytes())
+            .expect("writing score failed");
+        let mut file = archive.finish().expect("compressing failed");
+        file.set_position(0);
+        assert_eq!(read_mxl(file).expect("decompressing failed"), SCORE);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::core::{Note, NoteSeries, f64_to_usize, median, usize_to_f64};
+
+use super::{Polyphony, RawUnpaddedTargetMelody, UnpaddedInputMelody, target::NoteReducer};
+
+/// How a reference recording is divided into the notes of the target melody.
+#[derive(Debug, Clone)]
+pub struct SegmentOptions {
+    /// Semitones the pitch of a frame may differ from the median of the note so far before a new
+    /// note starts.
+    pub pitch_threshold: f64,
+    /// Notes shorter than this many seconds are dropped.
+    pub min_note_secs: f64,
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        Self {
+            pitch_threshold: 0.75,
+            min_note_secs: 0.06,
+        }
+    }
+}
+
+/// A note found in a reference recording, as a range of frames.
+struct Segment {
+    start: usize,
+    /// Note numbers of the frames of the note so far, sorted.
+    note_numbers: Vec<f64>,
+}
+
+impl Segment {
+    fn median(&self) -> f64 {
+        median(&self.note_numbers)
+    }
+
+    const fn end(&self) -> usize {
+        self.start + self.note_numbers.len()
+    }
+}
+
+/// Divides the voiced frames of a recording into steady notes, each at the median pitch of its
+/// frames.
+pub(super) fn segment(
+    frames: &NoteSeries,
+    min_frames: usize,
+    pitch_threshold: f64,
+) -> Vec<(usize, usize, f64)> {
+    let mut notes = Vec::new();
+    let mut current = None::<Segment>;
+    let mut close = |segment: Option<Segment>| {
+        if let Some(segment) = segment.filter(|s| s.note_numbers.len() >= min_frames) {
+            notes.push((segment.start, segment.end(), segment.median()));
+        }
+    };
+    for (i, frame) in frames.iter().enumerate() {
+        let Some(frame) = frame else {
+            close(current.take());
+            continue;
+        };
+        let n = frame.note_number;
+        match &mut current {
+            Some(segment) if (n - segment.median()).abs() <= pitch_threshold => {
+                let index = segment.note_numbers.partition_point(|&m| m < n);
+                segment.note_numbers.insert(index, n);
+            }
+            _ => close(current.replace(Segment {
+                start: i,
+                note_numbers: vec![n],
+            })),
+        }
+    }
+    close(current);
+    notes
+}
+
+impl RawUnpaddedTargetMelody {
+    /// Makes the target melody from the pitch analysed in a reference recording, such as a
+    /// teacher singing the melody.
+    ///
+    /// The pitch is followed frame by frame, with every run of voiced frames as one note, unless
+    /// `segmentation` divides it into steady notes first.
+    #[must_use]
+    pub fn from_recording(
+        recording: &UnpaddedInputMelody,
+        segmentation: Option<&SegmentOptions>,
+    ) -> Self {
+        let dt = *recording.notes.interval();
+        let frames = &**recording.notes.samples();
+        let mut reducer = NoteReducer::new(Polyphony::default());
+        let Some(options) = segmentation else {
+            // every run of voiced frames is a single note, bent to the pitch of every frame
+            let mut id = 0u32;
+            let mut held = false;
+            for (i, frame) in frames.iter().enumerate() {
+                let seconds = usize_to_f64(i) * dt;
+                match (frame, held) {
+                    (Some(frame), false) => {
+                        reducer.press(seconds, id, Note::new(frame.note_number));
+                    }
+                    (Some(frame), true) => reducer.bend(seconds, id, frame.note_number),
+                    (None, true) => {
+                        reducer.release(seconds, id);
+                        id += 1;
+                    }
+                    (None, false) => {}
+                }
+                held = frame.is_some();
+            }
+            reducer.release(usize_to_f64(frames.len()) * dt, id);
+            return reducer.finish(None);
+        };
+        let min_frames = f64_to_usize((options.min_note_secs / dt).ceil());
+        let notes = segment(frames, min_frames, options.pitch_threshold);
+        for ((start, end, note_number), id) in notes.into_iter().zip(0u32..) {
+            reducer.press(usize_to_f64(start) * dt, id, Note::new(note_number));
+            reducer.release(usize_to_f64(end) * dt, id);
+        }
+        reducer.finish(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{RawUnpaddedTargetMelody, SegmentOptions};
+    use crate::core::{DynNoteTimeSeries, Note, UnpaddedInputMelody};
+
+    #[rstest]
+    #[case(None, &[(0., Some(60.)), (0.1, Some(60.2)), (0.2, Some(60.1)), (0.3, Some(67.)), (0.4, None), (0.5, Some(64.)), (0.8, None)])]
+    #[case(Some(SegmentOptions { pitch_threshold: 0.5, min_note_secs: 0.15 }), &[(0., Some(60.1)), (0.3, None), (0.5, Some(64.)), (0.8, None)])]
+    fn recording(
+        #[case] segmentation: Option<SegmentOptions>,
+        #[case] expected: &[(f64, Option<f64>)],
+    ) {
+        // a wavering C4, a blip of a fifth above and a steady E4
+        let frames = [
+            Some(60.),
+            Some(60.2),
+            Some(60.1),
+            Some(67.),
+            None,
+            Some(64.),
+            Some(64.),
+            Some(64.),
+        ];
+        let recording = UnpaddedInputMelody {
+            notes: DynNoteTimeSeries::new(Vec::from(frames.map(|n| n.map(Note::new))), 0.1.into()),
+        };
+        let melody = RawUnpaddedTargetMelody::from_recording(&recording, segmentation.as_ref());
+        let events = melody
+            .note_events()
+            .iter()
+            .map(|event| {
+                (
+                    (*event.time * 1e6).round() / 1e6,
+                    event.value.as_ref().map(|n| n.note_number),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(events, expected);
+    }
+}
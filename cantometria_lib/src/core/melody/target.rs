@@ -1,73 +1,932 @@
-use std::{io::BufReader, path::Path};
+use std::{
+    borrow::Cow,
+    io::{BufReader, Read},
+    iter::Peekable,
+    path::Path,
+    sync::Arc,
+};
 
 use midi_file::{
     MidiFile,
-    core::Message,
-    file::{Division, Event, MetaEvent, MicrosecondsPerQuarter},
+    core::{Channel, Control, Message, NoteMessage},
+    file::{Event, MetaEvent, Track},
 };
 
+use ordered_float::OrderedFloat;
+
 use crate::{
-    core::{DynNonUniformNoteTimeSeries, Note, Timed, model::NonUniformNoteTimeSeriesRef},
-    error::{NewRawUnpaddedTargetMelodyError, OpenMidiError},
+    core::{
+        DynNonUniformNoteTimeSeries, Lyric, Note, Timed, Tuning, model::NonUniformNoteTimeSeriesRef,
+    },
+    error::{NewRawUnpaddedTargetMelodyError, OpenMidiError, OpenTargetError},
+};
+
+use super::{
+    SegmentOptions,
+    tempo::{Position, TempoMap, TimeDivision},
 };
 
-pub fn open_midi<P: AsRef<Path>>(path: P) -> Result<MidiFile, OpenMidiError> {
-    Ok(MidiFile::read(BufReader::new(std::fs::File::open(path)?))?)
+/// # Errors
+/// - opening the file failed
+/// - the file is malformed
+pub fn open_midi<P: AsRef<Path>>(path: P) -> Result<Midi, OpenMidiError> {
+    Midi::read(BufReader::new(std::fs::File::open(path)?))
 }
 
-pub struct RawUnpaddedTargetMelody {
+/// Reads the target melody from a MIDI file (`.mid`, `.midi` or `.kar`), an `UltraStar` song
+/// (`.txt`) or a `MusicXML` score (`.musicxml`, `.xml` or compressed `.mxl`), told apart by the
+/// extension of `path`.
+///
+/// # Errors
+/// - the extension is not one of the above
+/// - opening or reading the file failed
+pub fn open_target<P: AsRef<Path>>(
+    path: P,
+    options: &TargetOptions,
+    tuning: &Tuning,
+) -> Result<RawUnpaddedTargetMelody, OpenTargetError> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mid" | "midi" | "kar" => Ok(RawUnpaddedTargetMelody::new(
+            &open_midi(path)?,
+            options,
+            tuning,
+        )?),
+        "txt" => super::ultrastar::open_ultrastar(path, tuning),
+        "musicxml" | "xml" | "mxl" => super::musicxml::open_musicxml(path, options, tuning),
+        _ => Err(OpenTargetError::UnsupportedExtension(extension)),
+    }
+}
+
+/// A standard MIDI file with the time division of its header.
+pub struct Midi {
+    file: MidiFile,
+    division: TimeDivision,
+}
+
+impl Midi {
+    /// Reads a standard MIDI file, including SMPTE-timed files which `midi_file` rejects.
+    ///
+    /// # Errors
+    /// - reading failed
+    /// - the file is malformed
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, OpenMidiError> {
+        const DIVISION: std::ops::Range<usize> = 12..14;
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        // a header too short to hold the division fails to parse below
+        let division = TimeDivision::from_word(
+            bytes
+                .get(DIVISION)
+                .map_or(0, |word| u16::from_be_bytes([word[0], word[1]])),
+        );
+        if let TimeDivision::Smpte { .. } = division {
+            // the ticks are converted with the real division, so any placeholder will do
+            bytes[DIVISION].copy_from_slice(&1u16.to_be_bytes());
+        }
+        Ok(Self {
+            file: MidiFile::read(bytes.as_slice())?,
+            division,
+        })
+    }
+
+    #[must_use]
+    pub const fn file(&self) -> &MidiFile {
+        &self.file
+    }
+
+    #[must_use]
+    pub const fn division(&self) -> TimeDivision {
+        self.division
+    }
+
+    #[must_use]
+    pub fn tempo_map(&self) -> TempoMap {
+        TempoMap::new(&self.file, self.division)
+    }
+}
+
+/// Selects the track of a MIDI file, or the part of a `MusicXML` score, that holds the target
+/// melody.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TrackSelection {
+    /// The only track with notes on the selected channels, or part with notes in the selected
+    /// voice.
+    #[default]
+    Auto,
+    /// The track or part at the given zero-based index.
+    Index(u32),
+    /// The track with notes whose track name meta event, or the part whose part name, matches the
+    /// given name.
+    Name(String),
+    /// The track with notes whose first program change, or the part whose MIDI instrument, selects
+    /// the given zero-based program.
+    Program(u8),
+}
+
+/// Selects the note of the target melody while several notes of the track sound at once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Polyphony {
+    /// The note that started last, which follows overlapping legato notes.
+    #[default]
+    MostRecent,
+    /// The highest sounding note (skyline).
+    Highest,
+    /// The lowest sounding note.
+    Lowest,
+    /// Any sounding note, with the highest as the note the key is estimated from.
+    Any,
+}
+
+/// A note held in the target, with an identifier of the event that releases it.
+struct ActiveNote {
+    id: u32,
+    note: Note,
+}
+
+impl Polyphony {
+    /// Reduces the notes sounding at once, in the order they started, to a single note.
+    fn reduce(self, active: &[ActiveNote]) -> Option<Note> {
+        let note = &match self {
+            Self::MostRecent => active.last(),
+            Self::Highest | Self::Any => active
+                .iter()
+                .max_by(|a, b| a.note.note_number.total_cmp(&b.note.note_number)),
+            Self::Lowest => active
+                .iter()
+                .min_by(|a, b| a.note.note_number.total_cmp(&b.note.note_number)),
+        }?
+        .note;
+        let mut reduced = note.clone();
+        if self == Self::Any {
+            reduced.alternatives = active
+                .iter()
+                .map(|a| a.note.note_number)
+                .filter(|&n| OrderedFloat(n) != OrderedFloat(note.note_number))
+                .collect();
+        }
+        Some(reduced)
+    }
+}
+
+/// Builds the note events of a target from notes that may overlap, reduced to one note at a time.
+pub(super) struct NoteReducer {
+    polyphony: Polyphony,
+    active: Vec<ActiveNote>,
+    /// Number of notes started so far, which indexes the next one.
+    pressed: usize,
     note_events: DynNonUniformNoteTimeSeries,
 }
 
-impl RawUnpaddedTargetMelody {
-    pub fn new(midi: &MidiFile) -> Result<Self, NewRawUnpaddedTargetMelodyError> {
-        let tracks_len = midi.tracks_len();
-        let Division::QuarterNote(tpqn) = midi.header().division() else {
-            return Err(NewRawUnpaddedTargetMelodyError::UnsupportedDivisionType);
-        };
-        if tracks_len != 1 {
-            return Err(NewRawUnpaddedTargetMelodyError::MidiFileTracksLenNotOne(
-                tracks_len,
+impl NoteReducer {
+    pub(super) const fn new(polyphony: Polyphony) -> Self {
+        Self {
+            polyphony,
+            active: Vec::new(),
+            pressed: 0,
+            note_events: Vec::new(),
+        }
+    }
+
+    /// Starts `note` at `seconds`, to be released by `id`.
+    pub(super) fn press(&mut self, seconds: f64, id: u32, mut note: Note) {
+        note.index = Some(self.pressed);
+        self.pressed += 1;
+        self.active.push(ActiveNote { id, note });
+        self.update(seconds);
+    }
+
+    /// Releases the note started with `id` at `seconds`, if it is held.
+    pub(super) fn release(&mut self, seconds: f64, id: u32) {
+        if let Some(i) = self.active.iter().position(|a| a.id == id) {
+            self.active.remove(i);
+            self.update(seconds);
+        }
+    }
+
+    /// Shifts the held notes whose identifiers `matches` accepts by `semitones` at `seconds`.
+    pub(super) fn shift(&mut self, seconds: f64, matches: impl Fn(u32) -> bool, semitones: f64) {
+        let mut shifted = false;
+        for active in self.active.iter_mut().filter(|a| matches(a.id)) {
+            active.note.note_number += semitones;
+            shifted = true;
+        }
+        if shifted {
+            self.update(seconds);
+        }
+    }
+
+    /// Bends the note held with `id` to `note_number` at `seconds`, if it is held.
+    pub(super) fn bend(&mut self, seconds: f64, id: u32, note_number: f64) {
+        if let Some(active) = self.active.iter_mut().find(|a| a.id == id) {
+            active.note.note_number = note_number;
+            self.update(seconds);
+        }
+    }
+
+    fn update(&mut self, seconds: f64) {
+        let note = self.polyphony.reduce(&self.active);
+        // notes released and started at once leave no rest between them
+        if self
+            .note_events
+            .last()
+            .is_some_and(|last| last.time == OrderedFloat(seconds))
+        {
+            self.note_events.pop();
+        }
+        if self
+            .note_events
+            .last()
+            .is_none_or(|last| last.value != note)
+        {
+            self.note_events.push(Timed::new(seconds, note));
+        }
+    }
+
+    /// Ends the target, timed by `tempo_map` if it was written in bars and beats.
+    pub(super) fn finish(self, tempo_map: Option<TempoMap>) -> RawUnpaddedTargetMelody {
+        RawUnpaddedTargetMelody {
+            note_events: self.note_events,
+            tempo_map,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TargetOptions {
+    pub track: TrackSelection,
+    /// Zero-based MIDI channel whose notes form the melody, or every channel if `None`.
+    pub channel: Option<u8>,
+    /// `MusicXML` voice whose notes form the melody, or every voice if `None`.
+    pub voice: Option<String>,
+    pub polyphony: Polyphony,
+    /// Semitones a full pitch bend shifts the notes by until a channel sets its own range with
+    /// RPN 0, or `None` to ignore pitch bends.
+    pub pitch_bend_range: Option<f64>,
+    /// Divides the pitch of a reference recording used as the target into notes, or follows it
+    /// frame by frame if `None`.
+    pub segmentation: Option<SegmentOptions>,
+}
+
+impl Default for TargetOptions {
+    fn default() -> Self {
+        Self {
+            track: TrackSelection::default(),
+            channel: None,
+            voice: None,
+            polyphony: Polyphony::default(),
+            pitch_bend_range: Some(2.),
+            segmentation: None,
+        }
+    }
+}
+
+fn track_name(track: &Track) -> Option<Cow<'_, str>> {
+    track.events().find_map(|event| match event.event() {
+        Event::Meta(MetaEvent::TrackName(name)) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+fn track_program(track: &Track) -> Option<u8> {
+    track.events().find_map(|event| match event.event() {
+        Event::Midi(Message::ProgramChange(program_change)) => Some(program_change.program().get()),
+        _ => None,
+    })
+}
+
+impl TargetOptions {
+    fn has_channel(&self, channel: Channel) -> bool {
+        self.channel.is_none_or(|c| channel.get() == c)
+    }
+
+    fn has_notes(&self, track: &Track) -> bool {
+        track.events().any(|event| {
+            matches!(event.event(), Event::Midi(Message::NoteOn(note)) if self.has_channel(note.channel()))
+        })
+    }
+
+    fn select_track<'a>(
+        &self,
+        midi: &'a MidiFile,
+    ) -> Result<&'a Track, NewRawUnpaddedTargetMelodyError> {
+        if let TrackSelection::Index(index) = self.track {
+            return midi
+                .track(index)
+                .ok_or(NewRawUnpaddedTargetMelodyError::TrackNotFound);
+        }
+        let matches = midi
+            .tracks()
+            .zip(0..)
+            .filter(|(track, _)| {
+                self.has_notes(track)
+                    && match &self.track {
+                        TrackSelection::Auto | TrackSelection::Index(_) => true,
+                        TrackSelection::Name(name) => {
+                            track_name(track).is_some_and(|n| n.trim() == name)
+                        }
+                        TrackSelection::Program(program) => track_program(track) == Some(*program),
+                    }
+            })
+            .collect::<Box<_>>();
+        match *matches {
+            [(track, _)] => Ok(track),
+            [] => Err(NewRawUnpaddedTargetMelodyError::TrackNotFound),
+            _ => Err(NewRawUnpaddedTargetMelodyError::TrackAmbiguous(
+                matches.iter().map(|&(_, index)| index).collect(),
+            )),
+        }
+    }
+}
+
+/// Seconds after the start of a note within which a syllable of the lyrics is still sung on it.
+const LYRIC_TOLERANCE: f64 = 0.05;
+
+/// Reads the syllables of the lyrics of a MIDI file from its lyric meta events, or from its text
+/// events for karaoke files without any.
+///
+/// Syllables starting with `/` or `\` start a new line as in karaoke files, as do syllables
+/// ending with a carriage return or line feed. A syllable followed by a space starts a new word,
+/// while a trailing hyphen joins it to the next syllable.
+fn midi_lyrics(midi: &MidiFile, tempo_map: &TempoMap) -> Vec<Timed<Lyric>> {
+    let collect = |lyrics: bool| {
+        let mut texts = Vec::new();
+        for track in midi.tracks() {
+            let mut tick = 0.;
+            for event in track.events() {
+                tick += f64::from(event.delta_time());
+                match event.event() {
+                    Event::Meta(MetaEvent::Lyric(text)) if lyrics => texts.push((tick, text)),
+                    // karaoke files keep their headers in text events starting with @
+                    Event::Meta(MetaEvent::OtherText(text))
+                        if !lyrics && !text.as_str().starts_with('@') =>
+                    {
+                        texts.push((tick, text));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        texts.sort_by(|a, b| a.0.total_cmp(&b.0));
+        texts
+    };
+    let mut texts = collect(true);
+    if texts.is_empty() {
+        texts = collect(false);
+    }
+
+    let mut lyrics = Vec::new();
+    let (mut line, mut line_started, mut word_ended) = (0, false, false);
+    for (tick, text) in texts {
+        let text = text.as_str();
+        let mut text = &*text;
+        if let Some(rest) = text.strip_prefix(['/', '\\']) {
+            if line_started {
+                line += 1;
+                (line_started, word_ended) = (false, false);
+            }
+            text = rest;
+        }
+        let ends_line = text.ends_with(['\r', '\n']);
+        text = text.trim_end_matches(['\r', '\n']);
+        let starts_word = word_ended || text.starts_with(' ');
+        word_ended = text.ends_with(' ');
+        let word = text.trim().trim_end_matches('-');
+        if !word.is_empty() {
+            let syllable = if starts_word && line_started {
+                format!(" {word}")
+            } else {
+                word.to_owned()
+            };
+            lyrics.push(Timed::new(
+                tempo_map.seconds(tick),
+                Lyric { syllable, line },
             ));
+            line_started = true;
+        }
+        if ends_line && line_started {
+            line += 1;
+            (line_started, word_ended) = (false, false);
         }
-        // SAFETY: at this point, midi.tracks_len() == 1, so this is safe
-        let track = unsafe { midi.tracks().next().unwrap_unchecked() };
+    }
+    lyrics
+}
 
-        let mut last_tempo: Option<MicrosecondsPerQuarter> = None;
-        let mut note_events = Vec::new();
-        let mut time = 0.;
+/// Takes the syllables sung on a note starting at `seconds`, including any without a note of
+/// their own before it.
+fn take_lyric(
+    lyrics: &mut Peekable<impl Iterator<Item = Timed<Lyric>>>,
+    seconds: f64,
+) -> Option<Arc<Lyric>> {
+    let mut syllables = Vec::new();
+    while let Some(lyric) = lyrics.next_if(|lyric| *lyric.time <= seconds + LYRIC_TOLERANCE) {
+        syllables.push(lyric.value);
+    }
+    syllables.first().map(|first| {
+        Arc::new(Lyric {
+            syllable: syllables.iter().map(|l| &*l.syllable).collect(),
+            line: first.line,
+        })
+    })
+}
+
+/// The pitch bend of a MIDI channel.
+#[derive(Debug, Clone, Copy)]
+struct ChannelBend {
+    /// Semitones of a full bend.
+    range: f64,
+    /// Bend in `[-1.0, 1.0]`.
+    amount: f64,
+    /// Most and least significant bytes of the registered parameter selected for data entry.
+    parameter: [u8; 2],
+}
+
+impl ChannelBend {
+    /// Registered parameter number of the pitch bend range.
+    const RANGE_PARAMETER: [u8; 2] = [0, 0];
+    const CENTRE: u16 = 8192;
+
+    const fn new(range: f64) -> Self {
+        Self {
+            range,
+            amount: 0.,
+            parameter: [127, 127],
+        }
+    }
+
+    fn semitones(&self) -> f64 {
+        self.range * self.amount
+    }
+
+    /// Sets the bend to a 14-bit pitch bend value, returning the change in semitones.
+    fn bend(&mut self, value: u16) -> f64 {
+        let before = self.semitones();
+        let centre = f64::from(Self::CENTRE);
+        self.amount = (f64::from(value) - centre) / centre;
+        self.semitones() - before
+    }
+
+    /// Centres the bend and deselects the registered parameter, returning the change in semitones.
+    fn reset(&mut self) -> f64 {
+        self.parameter = Self::new(self.range).parameter;
+        self.bend(Self::CENTRE)
+    }
+
+    /// Follows the registered parameter messages setting the bend range, returning the change in
+    /// semitones.
+    fn control(&mut self, control: Control, value: u8) -> f64 {
+        let before = self.semitones();
+        let range_selected = self.parameter == Self::RANGE_PARAMETER;
+        match control {
+            Control::RegisteredParameterNumberMsb => self.parameter[0] = value,
+            Control::RegisteredParameterNumberLsb => self.parameter[1] = value,
+            // the most significant byte sets the semitones and the least the cents
+            Control::DataEntryMsb if range_selected => self.range = f64::from(value),
+            Control::DataEntryMsbLsb if range_selected => {
+                self.range = f64::from(value).mul_add(0.01, self.range.trunc());
+            }
+            _ => {}
+        }
+        self.semitones() - before
+    }
+}
+
+pub struct RawUnpaddedTargetMelody {
+    pub(super) note_events: DynNonUniformNoteTimeSeries,
+    pub(super) tempo_map: Option<TempoMap>,
+}
+
+impl RawUnpaddedTargetMelody {
+    /// Reads the melody of the track and channel chosen by `options`, reduced to one note at a time
+    /// as the options select and timed by the tempo changes in every track.
+    ///
+    /// The syllables of the lyrics in any track are attached to the notes they are sung on.
+    ///
+    /// # Errors
+    /// - no track or several tracks match the selection
+    /// - a note is not mapped by the tuning
+    pub fn new(
+        midi: &Midi,
+        options: &TargetOptions,
+        tuning: &Tuning,
+    ) -> Result<Self, NewRawUnpaddedTargetMelodyError> {
+        let track = options.select_track(&midi.file)?;
+        let tempo_map = midi.tempo_map();
+        let mut reducer = NoteReducer::new(options.polyphony);
+        let mut lyrics = midi_lyrics(&midi.file, &tempo_map).into_iter().peekable();
+        let mut tick = 0.;
+        // a note is released by a message with the same channel and key
+        let id = |note: &NoteMessage| {
+            u32::from(note.channel().get()) << 8 | u32::from(note.note_number().get())
+        };
+        let on_channel = |channel: u8| move |id: u32| id >> 8 == u32::from(channel);
+        let bend_range = options.pitch_bend_range;
+        let mut bends = [ChannelBend::new(bend_range.unwrap_or_default()); 16];
 
         for event in track.events() {
-            time += f64::from(event.delta_time() * last_tempo.unwrap_or_default().get())
-                / (f64::from(tpqn.get()) * 1e6);
+            tick += f64::from(event.delta_time());
             match event.event() {
-                Event::Midi(midi_event) => {
-                    match midi_event {
-                        Message::NoteOn(note_on)
-                            if note_on.velocity().get() > Note::VELOCITY_THRESHOLD =>
-                        {
-                            note_events.push(Timed::new(
-                                time,
-                                Some(crate::core::Note {
-                                    note_number: note_on.note_number().get().into(),
-                                }),
-                            ));
-                        }
-                        Message::NoteOn(_) | Message::NoteOff(_) => {
-                            note_events.push(Timed::new(time, None));
-                        }
-                        _ => {}
-                    };
+                Event::Midi(Message::NoteOn(note_on))
+                    if options.has_channel(note_on.channel())
+                        && note_on.velocity().get() > Note::VELOCITY_THRESHOLD =>
+                {
+                    let key = note_on.note_number().get();
+                    let note_number = tuning
+                        .note_to_note_number(key)
+                        .ok_or(NewRawUnpaddedTargetMelodyError::UnmappedNote(key))?;
+                    let seconds = tempo_map.seconds(tick);
+                    let channel = usize::from(note_on.channel().get());
+                    let mut note = Note::new(note_number + bends[channel].semitones());
+                    note.lyric = take_lyric(&mut lyrics, seconds);
+                    reducer.press(seconds, id(note_on), note);
+                }
+                Event::Midi(Message::NoteOn(note) | Message::NoteOff(note))
+                    if options.has_channel(note.channel()) =>
+                {
+                    reducer.release(tempo_map.seconds(tick), id(note));
+                }
+                Event::Midi(Message::PitchBend(bend))
+                    if bend_range.is_some() && options.has_channel(*bend.channel()) =>
+                {
+                    let channel = bend.channel().get();
+                    let semitones = bends[usize::from(channel)].bend(bend.pitch_bend().get());
+                    reducer.shift(tempo_map.seconds(tick), on_channel(channel), semitones);
+                }
+                Event::Midi(Message::Control(control))
+                    if bend_range.is_some() && options.has_channel(control.channel()) =>
+                {
+                    let channel = control.channel().get();
+                    let semitones = bends[usize::from(channel)]
+                        .control(control.control(), control.value().get());
+                    reducer.shift(tempo_map.seconds(tick), on_channel(channel), semitones);
+                }
+                Event::Midi(Message::ResetAllControllers(channel))
+                    if bend_range.is_some() && options.has_channel(*channel) =>
+                {
+                    let semitones = bends[usize::from(channel.get())].reset();
+                    reducer.shift(
+                        tempo_map.seconds(tick),
+                        on_channel(channel.get()),
+                        semitones,
+                    );
                 }
-                Event::Meta(MetaEvent::SetTempo(tempo)) => last_tempo = Some(*tempo),
                 _ => {}
             }
         }
 
-        Ok(Self { note_events })
+        Ok(reducer.finish(Some(tempo_map)))
     }
 
-    pub fn note_events(&self) -> NonUniformNoteTimeSeriesRef {
+    pub fn note_events(&self) -> NonUniformNoteTimeSeriesRef<'_> {
         &self.note_events
     }
+
+    /// Position in the target where the note with zero-based `index` starts, if the target is a
+    /// MIDI file or a score and the note was not dropped when reducing the notes to one at a time.
+    ///
+    /// The ticks of a score are quarter notes.
+    #[must_use]
+    pub fn note_position(&self, index: usize) -> Option<Position> {
+        let tempo_map = self.tempo_map.as_ref()?;
+        let start = self.note_events.iter().find(|event| {
+            event
+                .value
+                .as_ref()
+                .is_some_and(|note| note.index == Some(index))
+        })?;
+        Some(tempo_map.position(*start.time))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use midi_file::{
+        MidiFile, Settings, Text,
+        core::{Channel, GeneralMidi, NoteNumber, Velocity},
+        file::{Division, Event, MetaEvent, QuarterNoteDivision, QuartersPerMinute, Track},
+    };
+    use rstest::rstest;
+
+    use super::{Midi, Polyphony, RawUnpaddedTargetMelody, TargetOptions, TrackSelection};
+    use crate::core::Tuning;
+
+    const TPQN: u16 = 480;
+
+    /// Time, note number and alternatives of a target note event.
+    type ReducedEvent<'a> = (f64, Option<(f64, &'a [f64])>);
+
+    /// Pushes one quarter note on `channel` for each of `notes`, starting after `delay` ticks.
+    fn push_notes(track: &mut Track, channel: u8, delay: u32, notes: &[u8]) {
+        let channel = Channel::new(channel);
+        let velocity = Velocity::new(100);
+        for (i, &note) in notes.iter().enumerate() {
+            let delta = if i == 0 { delay } else { 0 };
+            track
+                .push_note_on(delta, channel, NoteNumber::new(note), velocity)
+                .expect("pushing note on failed");
+            track
+                .push_note_off(TPQN.into(), channel, NoteNumber::new(note), velocity)
+                .expect("pushing note off failed");
+        }
+    }
+
+    /// Writes `midi` and reads it back, with the division word of the header replaced if given.
+    fn encode(midi: &MidiFile, division: Option<u16>) -> Midi {
+        let mut bytes = Vec::new();
+        midi.write(&mut bytes).expect("writing midi failed");
+        if let Some(division) = division {
+            bytes[12..14].copy_from_slice(&division.to_be_bytes());
+        }
+        Midi::read(bytes.as_slice()).expect("reading midi failed")
+    }
+
+    /// A conductor track at 120 then 60 quarters per minute, a vocal track and a piano track.
+    fn multi_track() -> Midi {
+        let settings =
+            Settings::new().divisions(Division::QuarterNote(QuarterNoteDivision::new(TPQN)));
+        let mut midi = MidiFile::new_with_settings(settings);
+
+        let mut conductor = Track::default();
+        conductor
+            .push_tempo(0, QuartersPerMinute::new(120))
+            .expect("pushing tempo failed");
+        conductor
+            .push_tempo(u32::from(TPQN) * 2, QuartersPerMinute::new(60))
+            .expect("pushing tempo failed");
+        midi.push_track(conductor).expect("pushing track failed");
+
+        let mut vocals = Track::default();
+        vocals.set_name("Vocals").expect("naming track failed");
+        vocals
+            .set_general_midi(Channel::new(0), GeneralMidi::ChoirAahs)
+            .expect("setting program failed");
+        push_notes(&mut vocals, 0, 0, &[60, 62, 64]);
+        midi.push_track(vocals).expect("pushing track failed");
+
+        let mut piano = Track::default();
+        piano.set_name("Piano").expect("naming track failed");
+        piano
+            .set_general_midi(Channel::new(1), GeneralMidi::AcousticGrandPiano)
+            .expect("setting program failed");
+        push_notes(&mut piano, 1, 0, &[48, 55]);
+        midi.push_track(piano).expect("pushing track failed");
+        encode(&midi, None)
+    }
+
+    fn onsets(melody: &RawUnpaddedTargetMelody) -> Vec<(f64, f64)> {
+        melody
+            .note_events()
+            .iter()
+            .filter_map(|event| Some((*event.time, event.value.as_ref()?.note_number)))
+            .collect()
+    }
+
+    #[rstest]
+    #[case(TrackSelection::Index(1), None)]
+    #[case(TrackSelection::Name("Vocals".to_owned()), None)]
+    #[case(TrackSelection::Program(GeneralMidi::ChoirAahs as u8), None)]
+    #[case(TrackSelection::Auto, Some(0))]
+    fn select(#[case] track: TrackSelection, #[case] channel: Option<u8>) {
+        let options = TargetOptions {
+            track,
+            channel,
+            ..TargetOptions::default()
+        };
+        let melody = RawUnpaddedTargetMelody::new(&multi_track(), &options, &Tuning::default())
+            .expect("selecting track failed");
+        assert_eq!(onsets(&melody), [(0., 60.), (0.5, 62.), (1., 64.)]);
+    }
+
+    #[rstest]
+    #[case(TrackSelection::Auto, None)]
+    #[case(TrackSelection::Name("Drums".to_owned()), None)]
+    #[case(TrackSelection::Index(3), None)]
+    #[case(TrackSelection::Auto, Some(9))]
+    fn select_fails(#[case] track: TrackSelection, #[case] channel: Option<u8>) {
+        let options = TargetOptions {
+            track,
+            channel,
+            ..TargetOptions::default()
+        };
+        assert!(
+            RawUnpaddedTargetMelody::new(&multi_track(), &options, &Tuning::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn channel_filter() {
+        let mut midi = MidiFile::new();
+        let mut track = Track::default();
+        push_notes(&mut track, 0, 0, &[60]);
+        push_notes(&mut track, 3, 0, &[72, 74]);
+        midi.push_track(track).expect("pushing track failed");
+
+        let options = TargetOptions {
+            channel: Some(3),
+            ..TargetOptions::default()
+        };
+        let melody =
+            RawUnpaddedTargetMelody::new(&encode(&midi, None), &options, &Tuning::default())
+                .expect("selecting channel failed");
+        let notes = onsets(&melody)
+            .into_iter()
+            .map(|(_, n)| n)
+            .collect::<Vec<_>>();
+        assert_eq!(notes, [72., 74.]);
+    }
+
+    #[test]
+    fn positions() {
+        let melody = RawUnpaddedTargetMelody::new(
+            &multi_track(),
+            &TargetOptions {
+                channel: Some(0),
+                ..TargetOptions::default()
+            },
+            &Tuning::default(),
+        )
+        .expect("reading melody failed");
+        let position = melody.note_position(2).expect("note has no position");
+        assert!((position.seconds - 1.).abs() < 1e-9);
+        assert!((position.ticks - f64::from(TPQN * 2)).abs() < 1e-6);
+        assert_eq!(position.bar_beat.to_string(), "1:3.00");
+        assert!(melody.note_position(3).is_none());
+    }
+
+    #[test]
+    fn smpte() {
+        let mut midi = MidiFile::new();
+        let mut track = Track::default();
+        push_notes(&mut track, 0, 0, &[60, 62, 64]);
+        midi.push_track(track).expect("pushing track failed");
+
+        // 25 frames per second of 40 ticks each
+        let midi = encode(&midi, Some(0xE728));
+        let melody =
+            RawUnpaddedTargetMelody::new(&midi, &TargetOptions::default(), &Tuning::default())
+                .expect("reading melody failed");
+        assert_eq!(onsets(&melody), [(0., 60.), (0.48, 62.), (0.96, 64.)]);
+    }
+
+    #[test]
+    fn repeated_notes() {
+        let mut midi = MidiFile::new();
+        let mut track = Track::default();
+        push_notes(&mut track, 0, 0, &[60, 60]);
+        midi.push_track(track).expect("pushing track failed");
+
+        let melody = RawUnpaddedTargetMelody::new(
+            &encode(&midi, None),
+            &TargetOptions::default(),
+            &Tuning::default(),
+        )
+        .expect("reading melody failed");
+        let indices = melody
+            .note_events()
+            .iter()
+            .filter_map(|event| event.value.as_ref().map(|note| note.index))
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [Some(0), Some(1)]);
+    }
+
+    #[rstest]
+    #[case(Polyphony::MostRecent, &[(0., Some((60., &[][..]))), (0.25, Some((64., &[]))), (0.75, None)])]
+    #[case(Polyphony::Highest, &[(0., Some((60., &[][..]))), (0.25, Some((64., &[]))), (0.75, None)])]
+    #[case(Polyphony::Lowest, &[(0., Some((60., &[][..]))), (0.5, Some((64., &[]))), (0.75, None)])]
+    #[case(Polyphony::Any, &[(0., Some((60., &[][..]))), (0.25, Some((64., &[60.]))), (0.5, Some((64., &[]))), (0.75, None)])]
+    fn reduce(#[case] polyphony: Polyphony, #[case] expected: &[ReducedEvent]) {
+        let settings =
+            Settings::new().divisions(Division::QuarterNote(QuarterNoteDivision::new(TPQN)));
+        let mut midi = MidiFile::new_with_settings(settings);
+        let mut track = Track::default();
+        let (channel, velocity) = (Channel::new(0), Velocity::new(100));
+        let step = u32::from(TPQN) / 2;
+        // C4 and E4 overlap by an eighth note
+        for (delta, on, note) in [
+            (0, true, 60),
+            (step, true, 64),
+            (step, false, 60),
+            (step, false, 64),
+        ] {
+            let note = NoteNumber::new(note);
+            if on {
+                track.push_note_on(delta, channel, note, velocity)
+            } else {
+                track.push_note_off(delta, channel, note, velocity)
+            }
+            .expect("pushing note failed");
+        }
+        midi.push_track(track).expect("pushing track failed");
+
+        let options = TargetOptions {
+            polyphony,
+            ..TargetOptions::default()
+        };
+        let melody =
+            RawUnpaddedTargetMelody::new(&encode(&midi, None), &options, &Tuning::default())
+                .expect("reading melody failed");
+        let events = melody
+            .note_events()
+            .iter()
+            .map(|event| {
+                let note = event
+                    .value
+                    .as_ref()
+                    .map(|n| (n.note_number, &*n.alternatives));
+                (*event.time, note)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(events, expected);
+    }
+
+    #[rstest]
+    #[case(false, &["Hel-", "lo ", "world\r", "again"])]
+    #[case(true, &["@KMIDI KARAOKE FILE", "/Hel", "lo", " world", "\\again"])]
+    fn lyrics(#[case] karaoke: bool, #[case] texts: &[&str]) {
+        let mut midi = MidiFile::new();
+        let mut track = Track::default();
+        push_notes(&mut track, 0, 0, &[60, 62, 64, 65]);
+        let mut words = Track::default();
+        // karaoke headers come before the first note
+        let offset = texts.len() - 4;
+        for (i, &text) in texts.iter().enumerate() {
+            let delta = if i > offset { TPQN.into() } else { 0 };
+            if karaoke {
+                let event = Event::Meta(MetaEvent::OtherText(Text::new(text)));
+                words.push_event(delta, event)
+            } else {
+                words.push_lyric(delta, text)
+            }
+            .expect("pushing lyric failed");
+        }
+        midi.push_track(track).expect("pushing track failed");
+        midi.push_track(words).expect("pushing track failed");
+
+        let options = TargetOptions {
+            track: TrackSelection::Index(0),
+            ..TargetOptions::default()
+        };
+        let melody =
+            RawUnpaddedTargetMelody::new(&encode(&midi, None), &options, &Tuning::default())
+                .expect("reading melody failed");
+        let syllables = melody
+            .note_events()
+            .iter()
+            .filter_map(|event| {
+                let lyric = event.value.as_ref()?.lyric.as_ref()?;
+                Some((lyric.syllable.as_str(), lyric.line))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            syllables,
+            [("Hel", 0), ("lo", 0), (" world", 0), ("again", 1)]
+        );
+    }
+
+    #[rstest]
+    #[case(false, Some(2.), &[(0., 60.), (0.25, 61.)])]
+    #[case(true, Some(2.), &[(0., 60.), (0.25, 66.)])]
+    #[case(true, None, &[(0., 60.)])]
+    fn bend(
+        #[case] set_range: bool,
+        #[case] pitch_bend_range: Option<f64>,
+        #[case] expected: &[(f64, f64)],
+    ) {
+        // RPN 0 sets a range of 12 semitones, which `midi_file` cannot write
+        let rpn = [
+            0x00, 0xB0, 0x65, 0x00, 0x00, 0xB0, 0x64, 0x00, 0x00, 0xB0, 0x06, 0x0C,
+        ];
+        // a quarter of C4 bent up by half of the range after an eighth
+        let notes = [
+            0x00, 0x90, 0x3C, 0x64, 0x81, 0x70, 0xE0, 0x00, 0x60, 0x81, 0x70, 0x80, 0x3C, 0x00,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let events = if set_range { &rpn[..] } else { &[] }
+            .iter()
+            .chain(&notes)
+            .copied()
+            .collect::<Vec<u8>>();
+        let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x01\xE0MTrk".to_vec();
+        bytes.extend(
+            u32::try_from(events.len())
+                .expect("track too long")
+                .to_be_bytes(),
+        );
+        bytes.extend(events);
+
+        let options = TargetOptions {
+            pitch_bend_range,
+            ..TargetOptions::default()
+        };
+        let midi = Midi::read(bytes.as_slice()).expect("reading midi failed");
+        let melody = RawUnpaddedTargetMelody::new(&midi, &options, &Tuning::default())
+            .expect("reading melody failed");
+        assert_eq!(onsets(&melody), expected);
+        let last = melody.note_events().last().expect("no events");
+        assert!(last.value.is_none() && (*last.time - 0.5).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,336 @@
+use std::fmt;
+
+use ordered_float::OrderedFloat;
+
+use midi_file::{
+    MidiFile,
+    file::{Event, MetaEvent, MicrosecondsPerQuarter},
+};
+
+/// How the delta times of a MIDI file are measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeDivision {
+    /// Ticks per quarter note, so that tick durations follow the tempo.
+    TicksPerQuarter(u16),
+    /// SMPTE frames per second and ticks per frame, so that tick durations are absolute.
+    Smpte {
+        frames_per_second: f64,
+        ticks_per_frame: u8,
+    },
+}
+
+impl TimeDivision {
+    /// Decodes the division word of a MIDI file header.
+    pub(crate) fn from_word(word: u16) -> Self {
+        let [frames, ticks_per_frame] = word.to_be_bytes();
+        if frames & 0x80 == 0 {
+            return Self::TicksPerQuarter(word);
+        }
+        let frames_per_second = match frames.wrapping_neg() {
+            // 30 drop frame
+            29 => 30_000. / 1_001.,
+            frames => f64::from(frames),
+        };
+        Self::Smpte {
+            frames_per_second,
+            ticks_per_frame,
+        }
+    }
+}
+
+/// A position in the bars and beats of the time signatures of the target, both counted from one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarBeat {
+    pub bar: u32,
+    /// Beat within the bar, with the fraction of a beat elapsed.
+    pub beat: f64,
+}
+
+impl fmt::Display for BarBeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{:.2}", self.bar, self.beat)
+    }
+}
+
+/// A point of the target in seconds, ticks and bars and beats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub seconds: f64,
+    pub ticks: f64,
+    pub bar_beat: BarBeat,
+}
+
+fn seconds_per_quarter(tempo: MicrosecondsPerQuarter) -> f64 {
+    f64::from(tempo.get()) / 1e6
+}
+
+/// A stretch of constant tempo starting at a tempo change.
+#[derive(Debug, Clone, Copy)]
+struct TempoSegment {
+    tick: f64,
+    seconds: f64,
+    quarters: f64,
+    seconds_per_tick: f64,
+    quarters_per_tick: f64,
+}
+
+impl TempoSegment {
+    fn seconds(&self, tick: f64) -> f64 {
+        (tick - self.tick).mul_add(self.seconds_per_tick, self.seconds)
+    }
+
+    fn quarters(&self, tick: f64) -> f64 {
+        (tick - self.tick).mul_add(self.quarters_per_tick, self.quarters)
+    }
+}
+
+/// A stretch of constant time signature starting at a time signature change.
+#[derive(Debug, Clone, Copy)]
+struct MeterSegment {
+    quarters: f64,
+    /// Zero-based index of the bar the segment starts on.
+    bar: f64,
+    beats_per_bar: f64,
+    quarters_per_beat: f64,
+}
+
+/// Converts between the ticks of a MIDI file, seconds and bars and beats, following every tempo
+/// and time signature change in any of its tracks.
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    tempos: Box<[TempoSegment]>,
+    meters: Box<[MeterSegment]>,
+}
+
+impl TempoMap {
+    #[must_use]
+    pub fn new(midi: &MidiFile, division: TimeDivision) -> Self {
+        let mut tempo_changes = Vec::new();
+        let mut meter_changes = Vec::new();
+        for track in midi.tracks() {
+            let mut tick = 0.;
+            for event in track.events() {
+                tick += f64::from(event.delta_time());
+                match event.event() {
+                    Event::Meta(MetaEvent::SetTempo(tempo)) => {
+                        tempo_changes.push((tick, seconds_per_quarter(*tempo)));
+                    }
+                    Event::Meta(MetaEvent::TimeSignature(signature)) => meter_changes.push((
+                        tick,
+                        f64::from(signature.numerator()),
+                        4. / f64::from(1u8 << signature.denominator() as u8),
+                    )),
+                    _ => {}
+                }
+            }
+        }
+        Self::from_changes(division, tempo_changes, meter_changes)
+    }
+
+    /// Follows the tempo changes, given as the tick and seconds per quarter note of each, and the
+    /// time signature changes, given as the tick, beats per bar and quarter notes per beat of
+    /// each, in any order. The tempo is 120 quarter notes per minute and the time signature 4/4
+    /// until they first change.
+    pub(super) fn from_changes(
+        division: TimeDivision,
+        mut tempo_changes: Vec<(f64, f64)>,
+        mut meter_changes: Vec<(f64, f64, f64)>,
+    ) -> Self {
+        tempo_changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        meter_changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let segment = |tick, seconds, quarters, seconds_per_quarter: f64| {
+            let (seconds_per_tick, quarters_per_tick) = match division {
+                TimeDivision::TicksPerQuarter(tpqn) => {
+                    let tpqn = f64::from(tpqn);
+                    (seconds_per_quarter / tpqn, tpqn.recip())
+                }
+                TimeDivision::Smpte {
+                    frames_per_second,
+                    ticks_per_frame,
+                } => {
+                    let seconds_per_tick = (frames_per_second * f64::from(ticks_per_frame)).recip();
+                    (seconds_per_tick, seconds_per_tick / seconds_per_quarter)
+                }
+            };
+            TempoSegment {
+                tick,
+                seconds,
+                quarters,
+                seconds_per_tick,
+                quarters_per_tick,
+            }
+        };
+        let mut tempos = vec![segment(
+            0.,
+            0.,
+            0.,
+            seconds_per_quarter(MicrosecondsPerQuarter::default()),
+        )];
+        for (tick, tempo) in tempo_changes {
+            let last = tempos[tempos.len() - 1];
+            if OrderedFloat(last.tick) == OrderedFloat(tick) {
+                tempos.pop();
+            }
+            tempos.push(segment(
+                tick,
+                last.seconds(tick),
+                last.quarters(tick),
+                tempo,
+            ));
+        }
+
+        let mut map = Self {
+            tempos: tempos.into(),
+            meters: Box::new([]),
+        };
+        let meter = |quarters, bar, beats_per_bar, quarters_per_beat| MeterSegment {
+            quarters,
+            bar,
+            beats_per_bar,
+            quarters_per_beat,
+        };
+        let mut meters = vec![meter(0., 0., 4., 1.)];
+        for (tick, beats_per_bar, quarters_per_beat) in meter_changes {
+            let quarters = map.quarters(tick);
+            let last = meters[meters.len() - 1];
+            if OrderedFloat(last.quarters) == OrderedFloat(quarters) {
+                meters.pop();
+            }
+            // a change part way through a bar starts a new bar
+            let bar = last.bar
+                + ((quarters - last.quarters) / (last.quarters_per_beat * last.beats_per_bar))
+                    .ceil();
+            meters.push(meter(quarters, bar, beats_per_bar, quarters_per_beat));
+        }
+        map.meters = meters.into();
+        map
+    }
+
+    fn tempo_at_tick(&self, tick: f64) -> &TempoSegment {
+        let index = self.tempos.partition_point(|segment| segment.tick <= tick);
+        &self.tempos[index.saturating_sub(1)]
+    }
+
+    fn quarters(&self, tick: f64) -> f64 {
+        self.tempo_at_tick(tick).quarters(tick)
+    }
+
+    /// Time in seconds from the start of the file at `tick`.
+    #[must_use]
+    pub fn seconds(&self, tick: f64) -> f64 {
+        self.tempo_at_tick(tick).seconds(tick)
+    }
+
+    /// Tick at time `seconds` from the start of the file.
+    #[must_use]
+    pub fn ticks(&self, seconds: f64) -> f64 {
+        let index = self
+            .tempos
+            .partition_point(|segment| segment.seconds <= seconds);
+        let segment = &self.tempos[index.saturating_sub(1)];
+        (seconds - segment.seconds) / segment.seconds_per_tick + segment.tick
+    }
+
+    /// Bar and beat at `tick`.
+    #[must_use]
+    pub fn bar_beat(&self, tick: f64) -> BarBeat {
+        let quarters = self.quarters(tick);
+        let index = self
+            .meters
+            .partition_point(|segment| segment.quarters <= quarters);
+        let segment = &self.meters[index.saturating_sub(1)];
+        let beats = (quarters - segment.quarters) / segment.quarters_per_beat;
+        let bars = (beats / segment.beats_per_bar).floor();
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        BarBeat {
+            bar: (segment.bar + bars) as u32 + 1,
+            beat: bars.mul_add(-segment.beats_per_bar, beats) + 1.,
+        }
+    }
+
+    /// Tick and bar and beat at time `seconds` from the start of the file.
+    #[must_use]
+    pub fn position(&self, seconds: f64) -> Position {
+        let ticks = self.ticks(seconds);
+        Position {
+            seconds,
+            ticks,
+            bar_beat: self.bar_beat(ticks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use midi_file::{
+        MidiFile,
+        core::{Clocks, DurationName},
+        file::{QuartersPerMinute, Track},
+    };
+    use rstest::rstest;
+
+    use super::{BarBeat, TempoMap, TimeDivision};
+
+    /// A 4/4 bar at 120 quarters per minute, then 3/8 bars at 60 quarters per minute.
+    fn changes() -> MidiFile {
+        let mut midi = MidiFile::new();
+        let mut conductor = Track::default();
+        conductor
+            .push_time_signature(0, 4, DurationName::Quarter, Clocks::Quarter)
+            .expect("pushing time signature failed");
+        conductor
+            .push_time_signature(4 * 480, 3, DurationName::Eighth, Clocks::DottedQuarter)
+            .expect("pushing time signature failed");
+        midi.push_track(conductor).expect("pushing track failed");
+        // the tempo changes are on another track
+        let mut tempo = Track::default();
+        tempo
+            .push_tempo(0, QuartersPerMinute::new(120))
+            .expect("pushing tempo failed");
+        tempo
+            .push_tempo(4 * 480, QuartersPerMinute::new(60))
+            .expect("pushing tempo failed");
+        midi.push_track(tempo).expect("pushing track failed");
+        midi
+    }
+
+    #[rstest]
+    #[case(TimeDivision::TicksPerQuarter(480), 0., 0., 1, 1.)]
+    #[case(TimeDivision::TicksPerQuarter(480), 720., 0.75, 1, 2.5)]
+    #[case(TimeDivision::TicksPerQuarter(480), 1920., 2., 2, 1.)]
+    #[case(TimeDivision::TicksPerQuarter(480), 2640., 3.5, 3, 1.)]
+    #[case(TimeDivision::from_word(0xE728), 1000., 1., 1, 3.)]
+    #[case(TimeDivision::from_word(0xE728), 2920., 2.92, 2, 3.)]
+    fn convert(
+        #[case] division: TimeDivision,
+        #[case] tick: f64,
+        #[case] seconds: f64,
+        #[case] bar: u32,
+        #[case] beat: f64,
+    ) {
+        let map = TempoMap::new(&changes(), division);
+        assert!((map.seconds(tick) - seconds).abs() < 1e-9);
+        assert!((map.ticks(seconds) - tick).abs() < 1e-6);
+        let position = map.position(seconds);
+        assert!((position.ticks - tick).abs() < 1e-6);
+        assert_eq!(position.bar_beat.bar, bar);
+        assert!((position.bar_beat.beat - beat).abs() < 1e-6);
+    }
+
+    #[test]
+    fn division() {
+        assert_eq!(
+            TimeDivision::from_word(0x01E0),
+            TimeDivision::TicksPerQuarter(480)
+        );
+        assert_eq!(
+            TimeDivision::from_word(0xE250),
+            TimeDivision::Smpte {
+                frames_per_second: 30.,
+                ticks_per_frame: 80
+            }
+        );
+        assert_eq!(BarBeat { bar: 3, beat: 1.5 }.to_string(), "3:1.50");
+    }
+}
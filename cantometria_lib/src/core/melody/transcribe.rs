@@ -0,0 +1,206 @@
+use midi_file::{
+    MidiFile, Settings,
+    core::{Channel, GeneralMidi, NoteNumber, PitchBendValue, Velocity},
+    file::{Division, QuarterNoteDivision, QuartersPerMinute, Track},
+};
+
+use crate::core::{f64_to_usize, usize_to_f64};
+
+use super::{SegmentOptions, UnpaddedInputMelody, reference::segment};
+
+const TICKS_PER_QUARTER: u16 = 480;
+const QUARTERS_PER_MINUTE: u8 = 120;
+/// Semitones of a full pitch bend, which is the default range as the range cannot be written.
+const BEND_RANGE: f64 = 2.;
+
+/// How a sung recording is written as a MIDI file.
+#[derive(Debug, Clone)]
+pub struct TranscribeOptions {
+    pub segmentation: SegmentOptions,
+    /// Follows the sung pitch within each note with pitch bends, up to two semitones either side
+    /// of the note.
+    pub pitch_bend: bool,
+    pub velocity: u8,
+}
+
+impl Default for TranscribeOptions {
+    fn default() -> Self {
+        Self {
+            segmentation: SegmentOptions::default(),
+            pitch_bend: false,
+            velocity: 100,
+        }
+    }
+}
+
+/// 14-bit pitch bend value that bends a note by `semitones`.
+fn pitch_bend(semitones: f64) -> PitchBendValue {
+    let centre = f64::from(PitchBendValue::default().get());
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    PitchBendValue::new(
+        (semitones / BEND_RANGE)
+            .clamp(-1., 1.)
+            .mul_add(centre, centre)
+            .round() as u16,
+    )
+}
+
+/// Builds a single track of timed events from their absolute ticks.
+struct TrackWriter {
+    track: Track,
+    tick: u32,
+    channel: Channel,
+    bend: PitchBendValue,
+}
+
+impl TrackWriter {
+    fn delta(&mut self, tick: u32) -> u32 {
+        let delta = tick.saturating_sub(self.tick);
+        self.tick = self.tick.max(tick);
+        delta
+    }
+
+    /// Bends the channel to `value` at `tick` unless it is bent there already.
+    fn bend(&mut self, tick: u32, value: PitchBendValue) -> Result<(), midi_file::Error> {
+        if value != self.bend {
+            let delta = self.delta(tick);
+            self.track.push_pitch_bend(delta, self.channel, value)?;
+            self.bend = value;
+        }
+        Ok(())
+    }
+}
+
+impl UnpaddedInputMelody {
+    /// Transcribes the sung pitch into a standard MIDI file, with a note for every steady note
+    /// that `options` segments the pitch into.
+    ///
+    /// Each note is written at the nearest key to its median pitch, at 120 quarter notes per
+    /// minute so that a quarter note lasts half a second.
+    ///
+    /// # Errors
+    /// - the events do not fit in a MIDI file
+    pub fn to_midi(&self, options: &TranscribeOptions) -> Result<MidiFile, midi_file::Error> {
+        let dt = *self.notes.interval();
+        let frames = &**self.notes.samples();
+        let min_frames = f64_to_usize((options.segmentation.min_note_secs / dt).ceil());
+        let ticks_per_frame =
+            dt * f64::from(QUARTERS_PER_MINUTE) / 60. * f64::from(TICKS_PER_QUARTER);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let tick = |frame: usize| (usize_to_f64(frame) * ticks_per_frame).round() as u32;
+
+        let channel = Channel::new(0);
+        let velocity = Velocity::new(options.velocity);
+        let mut writer = TrackWriter {
+            track: Track::default(),
+            tick: 0,
+            channel,
+            bend: PitchBendValue::default(),
+        };
+        writer.track.set_name("Vocals")?;
+        writer
+            .track
+            .set_general_midi(channel, GeneralMidi::ChoirAahs)?;
+        writer
+            .track
+            .push_tempo(0, QuartersPerMinute::new(QUARTERS_PER_MINUTE))?;
+
+        for (start, end, note_number) in
+            segment(frames, min_frames, options.segmentation.pitch_threshold)
+        {
+            let key = note_number.round();
+            if !(0. ..128.).contains(&key) {
+                continue;
+            }
+            let bend = |frame: usize| {
+                frames[frame]
+                    .as_ref()
+                    .map_or_else(PitchBendValue::default, |n| pitch_bend(n.note_number - key))
+            };
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let key = NoteNumber::new(key as u8);
+
+            if options.pitch_bend {
+                writer.bend(tick(start), bend(start))?;
+            }
+            let delta = writer.delta(tick(start));
+            writer.track.push_note_on(delta, channel, key, velocity)?;
+            if options.pitch_bend {
+                for frame in start + 1..end {
+                    writer.bend(tick(frame), bend(frame))?;
+                }
+            }
+            let delta = writer.delta(tick(end));
+            writer.track.push_note_off(delta, channel, key, velocity)?;
+            writer.bend(tick(end), PitchBendValue::default())?;
+        }
+
+        let settings = Settings::new().divisions(Division::QuarterNote(QuarterNoteDivision::new(
+            TICKS_PER_QUARTER,
+        )));
+        let mut midi = MidiFile::new_with_settings(settings);
+        midi.push_track(writer.track)?;
+        Ok(midi)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::TranscribeOptions;
+    use crate::core::{
+        DynNoteTimeSeries, Midi, Note, RawUnpaddedTargetMelody, SegmentOptions, TargetOptions,
+        Tuning, UnpaddedInputMelody,
+    };
+
+    #[rstest]
+    #[case(false, &[(0., Some(60.)), (0.3, None), (0.5, Some(64.)), (0.8, None)])]
+    #[case(true, &[(0., Some(60.)), (0.1, Some(60.2)), (0.2, Some(60.1)), (0.3, None), (0.5, Some(64.)), (0.8, None)])]
+    fn round_trip(#[case] pitch_bend: bool, #[case] expected: &[(f64, Option<f64>)]) {
+        let frames = [
+            Some(60.),
+            Some(60.2),
+            Some(60.1),
+            Some(67.),
+            None,
+            Some(64.),
+            Some(64.),
+            Some(64.),
+        ];
+        let recording = UnpaddedInputMelody {
+            notes: DynNoteTimeSeries::new(Vec::from(frames.map(|n| n.map(Note::new))), 0.1.into()),
+        };
+        let options = TranscribeOptions {
+            segmentation: SegmentOptions {
+                pitch_threshold: 0.5,
+                min_note_secs: 0.15,
+            },
+            pitch_bend,
+            ..TranscribeOptions::default()
+        };
+        let mut bytes = Vec::new();
+        recording
+            .to_midi(&options)
+            .expect("transcribing failed")
+            .write(&mut bytes)
+            .expect("writing midi failed");
+
+        let midi = Midi::read(bytes.as_slice()).expect("reading midi failed");
+        let melody =
+            RawUnpaddedTargetMelody::new(&midi, &TargetOptions::default(), &Tuning::default())
+                .expect("reading melody failed");
+        let round = |x: f64| (x * 1e3).round() / 1e3;
+        let events = melody
+            .note_events()
+            .iter()
+            .map(|event| {
+                (
+                    round(*event.time),
+                    event.value.as_ref().map(|n| round(n.note_number)),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(events, expected);
+    }
+}
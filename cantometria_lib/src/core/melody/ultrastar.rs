@@ -0,0 +1,268 @@
+use std::{borrow::Cow, path::Path, sync::Arc};
+
+use crate::{
+    core::{Lyric, Note, NoteKind, Timed, Tuning},
+    error::{OpenTargetError, ParseUltraStarError},
+};
+
+use super::RawUnpaddedTargetMelody;
+
+/// MIDI note number of pitch zero in an `UltraStar` song.
+const PITCH_ZERO: i32 = 60;
+
+/// Parses a header value, which `UltraStar` songs may write with a decimal comma.
+fn parse_header(key: &str, value: &str) -> Result<f64, ParseUltraStarError> {
+    value
+        .trim()
+        .replace(',', ".")
+        .parse()
+        .map_err(|_| ParseUltraStarError::InvalidHeader(key.to_owned(), value.to_owned()))
+}
+
+/// Parses the beat numbers of a note or line break line.
+fn parse_beat(line_number: usize, word: Option<&str>) -> Result<i32, ParseUltraStarError> {
+    word.and_then(|w| w.trim().parse().ok())
+        .ok_or(ParseUltraStarError::InvalidLine(line_number))
+}
+
+/// Splits the first word off `text`, skipping any whitespace before it.
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    text.split_at(text.find(char::is_whitespace).unwrap_or(text.len()))
+}
+
+/// Characters of Windows-1252 for the bytes `0x80..0xA0`, where it differs from Latin-1.
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// Decodes the text of a song, which is UTF-8 if it is valid as such and Windows-1252 otherwise,
+/// as older songs were written in it or in Latin-1.
+fn decode(bytes: &[u8]) -> Cow<'_, str> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    std::str::from_utf8(bytes).map_or_else(
+        |_| {
+            bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..0xA0 => WINDOWS_1252[usize::from(b - 0x80)],
+                    _ => char::from(b),
+                })
+                .collect()
+        },
+        Cow::Borrowed,
+    )
+}
+
+impl RawUnpaddedTargetMelody {
+    /// Reads the melody of an `UltraStar` song, keeping the syllable of every note and whether it
+    /// is golden or freestyle.
+    ///
+    /// Rap notes are read as freestyle. Only the first singer of a duet is read.
+    ///
+    /// # Errors
+    /// - the `#BPM` header is missing or any header used is malformed
+    /// - a note or line break is malformed
+    /// - a note is out of the MIDI range or not mapped by the tuning
+    pub fn from_ultrastar(song: &str, tuning: &Tuning) -> Result<Self, ParseUltraStarError> {
+        let mut seconds_per_beat = None;
+        let mut gap = 0.;
+        let mut relative = false;
+        let mut offset = 0;
+        let mut line = 0;
+        let mut first_singer = true;
+        let mut note_events = Vec::<Timed<Option<Note>>>::new();
+        let mut notes = 0;
+
+        for (i, text) in song.lines().enumerate() {
+            let line_number = i + 1;
+            let text = text.trim_end_matches('\r');
+            if let Some(header) = text.strip_prefix('#') {
+                let Some((key, value)) = header.split_once(':') else {
+                    continue;
+                };
+                match key.trim().to_ascii_uppercase().as_str() {
+                    // UltraStar beats are quarters of the beats of the tempo
+                    "BPM" => seconds_per_beat = Some(15. / parse_header(key, value)?),
+                    "GAP" => gap = parse_header(key, value)? / 1000.,
+                    "RELATIVE" => relative = value.trim().eq_ignore_ascii_case("yes"),
+                    _ => {}
+                }
+                continue;
+            }
+            let Some(kind) = text.chars().next() else {
+                continue;
+            };
+            let rest = &text[kind.len_utf8()..];
+            match kind {
+                'E' => break,
+                'P' => first_singer = rest.trim() == "1",
+                '-' if first_singer => {
+                    line += 1;
+                    if relative {
+                        let mut words = rest.split_whitespace();
+                        let start = parse_beat(line_number, words.next())?;
+                        offset += words
+                            .next()
+                            .map_or(Ok(start), |w| parse_beat(line_number, Some(w)))?;
+                    }
+                }
+                ':' | '*' | 'F' | 'R' | 'G' if first_singer => {
+                    let (start, rest) = split_word(rest);
+                    let (length, rest) = split_word(rest);
+                    let (pitch, rest) = split_word(rest);
+                    let start = offset + parse_beat(line_number, Some(start))?;
+                    let length = parse_beat(line_number, Some(length))?;
+                    let pitch = parse_beat(line_number, Some(pitch))?;
+                    // the syllable follows a single separator and may itself start with a space
+                    let syllable = rest
+                        .strip_prefix(char::is_whitespace)
+                        .unwrap_or(rest)
+                        .to_owned();
+
+                    let seconds_per_beat =
+                        seconds_per_beat.ok_or(ParseUltraStarError::MissingBpm)?;
+                    let time = |beat: i32| f64::from(beat).mul_add(seconds_per_beat, gap);
+                    let key = u8::try_from(PITCH_ZERO + pitch)
+                        .ok()
+                        .filter(|&key| key < 128)
+                        .ok_or(ParseUltraStarError::PitchOutOfRange(pitch))?;
+                    let mut note = Note::new(
+                        tuning
+                            .note_to_note_number(key)
+                            .ok_or(ParseUltraStarError::UnmappedNote(key))?,
+                    );
+                    note.kind = match kind {
+                        '*' => NoteKind::Golden,
+                        'F' | 'R' | 'G' => NoteKind::Freestyle,
+                        _ => NoteKind::Normal,
+                    };
+                    note.lyric = Some(Arc::new(Lyric { syllable, line }));
+                    note.index = Some(notes);
+                    notes += 1;
+
+                    let (start, end) = (time(start), time(start + length));
+                    // the note starts as the previous one ends
+                    if note_events
+                        .last()
+                        .is_some_and(|last| last.value.is_none() && *last.time >= start)
+                    {
+                        note_events.pop();
+                    }
+                    note_events.push(Timed::new(start, Some(note)));
+                    note_events.push(Timed::new(end, None));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            note_events,
+            tempo_map: None,
+        })
+    }
+}
+
+/// Reads the melody of the `UltraStar` song at `path`.
+///
+/// # Errors
+/// See [`RawUnpaddedTargetMelody::from_ultrastar`].
+pub fn open_ultrastar<P: AsRef<Path>>(
+    path: P,
+    tuning: &Tuning,
+) -> Result<RawUnpaddedTargetMelody, OpenTargetError> {
+    let song = std::fs::read(path)?;
+    Ok(RawUnpaddedTargetMelody::from_ultrastar(
+        &decode(&song),
+        tuning,
+    )?)
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{RawUnpaddedTargetMelody, decode};
+    use crate::core::{NoteKind, Tuning};
+
+    /// Onset, note number, kind, syllable and line of every note of `song`.
+    fn notes(song: &str) -> Vec<(f64, f64, NoteKind, String, usize)> {
+        let melody = RawUnpaddedTargetMelody::from_ultrastar(song, &Tuning::default())
+            .expect("parsing failed");
+        melody
+            .note_events()
+            .iter()
+            .filter_map(|event| {
+                let note = event.value.as_ref()?;
+                let lyric = note.lyric.as_ref()?;
+                Some((
+                    (*event.time * 1e6).round() / 1e6,
+                    note.note_number,
+                    note.kind,
+                    lyric.syllable.clone(),
+                    lyric.line,
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse() {
+        let song =
+            "#TITLE:Test\n#BPM:300\n#GAP:1000\n: 0 4 0 Hel\n* 4 4 2 lo\n- 10\nF 12 2 4  world\nE\n";
+        assert_eq!(
+            notes(song),
+            [
+                (1., 60., NoteKind::Normal, "Hel".to_owned(), 0),
+                (1.2, 62., NoteKind::Golden, "lo".to_owned(), 0),
+                (1.6, 64., NoteKind::Freestyle, " world".to_owned(), 1),
+            ]
+        );
+        let melody = RawUnpaddedTargetMelody::from_ultrastar(song, &Tuning::default())
+            .expect("parsing failed");
+        let last = melody.note_events().last().expect("no events");
+        assert!(last.value.is_none() && (*last.time - 1.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn relative_duet() {
+        let song =
+            "#BPM:300,0\n#RELATIVE:YES\nP1\n: 0 2 0 a\n- 4 10\n: 0 2 0 b\nP2\n: 0 2 7 c\nE\n";
+        assert_eq!(
+            notes(song),
+            [
+                (0., 60., NoteKind::Normal, "a".to_owned(), 0),
+                (0.5, 60., NoteKind::Normal, "b".to_owned(), 1),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case("#BPM:300\n:  0   2 0 a\n")]
+    #[case("#BPM:300\n:\t0\t2\t0\ta\n")]
+    #[case("#BPM:300\r\n: 0 2 0 a\r\n")]
+    fn whitespace(#[case] song: &str) {
+        assert_eq!(
+            notes(song),
+            [(0., 60., NoteKind::Normal, "a".to_owned(), 0)]
+        );
+    }
+
+    #[rstest]
+    #[case(b"#BPM:300\n: 0 2 0 Ca\xF1\xE9\x92s\n", "Cañé’s")]
+    #[case(b"\xEF\xBB\xBF#BPM:300\n: 0 2 0 Ca\xC3\xB1\n", "Cañ")]
+    fn encoding(#[case] bytes: &[u8], #[case] syllable: &str) {
+        let song = decode(bytes);
+        assert_eq!(notes(&song)[0].3, syllable);
+    }
+
+    #[rstest]
+    #[case(": 0 2 0 a\n")]
+    #[case("#BPM:fast\n: 0 2 0 a\n")]
+    #[case("#BPM:300\n: 0 two 0 a\n")]
+    #[case("#BPM:300\n: 0 2 100 a\n")]
+    fn parse_fails(#[case] song: &str) {
+        assert!(RawUnpaddedTargetMelody::from_ultrastar(song, &Tuning::default()).is_err());
+    }
+}
@@ -1,18 +1,32 @@
 mod melody;
 mod model;
+mod pitch;
+mod tuning;
 
-pub use melody::{RawUnpaddedTargetMelody, UnpaddedInputMelody, open_midi, open_wav};
+pub use melody::{
+    BarBeat, ChannelMode, InputOptions, Midi, Polyphony, Position, RawUnpaddedTargetMelody,
+    SegmentOptions, TargetOptions, TempoMap, TimeDivision, TrackSelection, TranscribeOptions,
+    UnpaddedInputMelody, WindowFunction, open_midi, open_target, open_wav,
+};
 #[cfg(feature = "visualise")]
 pub use model::Time;
 pub use model::{
-    DynNonUniformNoteTimeSeries, DynNoteTimeSeries, Note, NoteSeries, NoteTimeSeries, Timed,
+    Detection, DynNonUniformNoteTimeSeries, DynNoteTimeSeries, Lyric, Note, NoteKind, NoteSeries,
+    NoteTimeSeries, Timed,
 };
+pub use pitch::{Cepstrum, HarmonicProductSpectrum, McLeod, PYin, Pitch, PitchEstimator, Yin};
+pub use tuning::Tuning;
 
 #[allow(clippy::cast_precision_loss)]
 pub const fn usize_to_f64(value: usize) -> f64 {
     value as f64
 }
 
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub const fn f64_to_usize(value: f64) -> usize {
+    value as usize
+}
+
 #[allow(clippy::cast_precision_loss)]
 pub const fn isize_to_f64(value: isize) -> f64 {
     value as f64
@@ -27,5 +41,5 @@ pub const fn median(arr: &[f64]) -> f64 {
     if arr.len() % 2 == 1 {
         return arr[arr.len() / 2];
     }
-    (arr[arr.len() / 2 - 1] + arr[arr.len() / 2]) / 2.
+    f64::midpoint(arr[arr.len() / 2 - 1], arr[arr.len() / 2])
 }
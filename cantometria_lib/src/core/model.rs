@@ -1,4 +1,4 @@
-use std::ops::Deref;
+use std::{ops::Deref, sync::Arc};
 
 use ordered_float::OrderedFloat;
 
@@ -27,6 +27,13 @@ where
         &self.samples
     }
 
+    pub fn samples_mut(&mut self) -> &mut NoteSeries
+    where
+        A: std::ops::DerefMut,
+    {
+        &mut self.samples
+    }
+
     pub const fn interval(&self) -> OrderedFloat<f64> {
         self.interval
     }
@@ -88,14 +95,107 @@ impl<T: Clone> Timed<T> {
     }
 }
 
+/// Measurements of the analysis frame a sung note was detected in.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Detection {
+    /// Frequency estimated by the pitch detector in hertz, before any post-processing of the note
+    /// number.
+    pub frequency: f64,
+    /// Confidence of the pitch detector in `[0.0, 1.0]`.
+    pub clarity: f64,
+    /// Root mean square level of the frame relative to full scale.
+    pub rms: f64,
+}
+
+impl Detection {
+    /// Level of the frame in decibels relative to full scale.
+    pub fn decibels(&self) -> f64 {
+        20. * self.rms.log10()
+    }
+}
+
+/// How a target note is graded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd)]
+pub enum NoteKind {
+    #[default]
+    Normal,
+    /// Counts double towards the pitch grade.
+    Golden,
+    /// Must be sung, but at any pitch.
+    Freestyle,
+}
+
+/// A syllable of the lyrics sung on a target note.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd)]
+pub struct Lyric {
+    /// Text of the syllable, with any spaces separating it from the previous syllable.
+    pub syllable: String,
+    /// Zero-based index of the line of the lyrics the syllable is on.
+    pub line: usize,
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[allow(clippy::struct_field_names)]
 pub struct Note {
     pub note_number: f64,
+    /// Other note numbers sounding in the target at the same time that are equally acceptable to
+    /// sing.
+    pub alternatives: Vec<f64>,
+    pub kind: NoteKind,
+    /// Syllable sung on a target note, shared by every frame of the note.
+    pub lyric: Option<Arc<Lyric>>,
+    /// Present only on notes detected in a recording.
+    pub detection: Option<Detection>,
+    /// Zero-based index of a target note in its melody, shared by every frame and pitch bend of
+    /// the note.
+    pub index: Option<usize>,
 }
 
 impl Note {
     pub const VELOCITY_THRESHOLD: u8 = 0;
     pub const fn new(note_number: f64) -> Self {
-        Self { note_number }
+        Self {
+            note_number,
+            alternatives: Vec::new(),
+            kind: NoteKind::Normal,
+            lyric: None,
+            detection: None,
+            index: None,
+        }
+    }
+
+    pub const fn detected(note_number: f64, detection: Detection) -> Self {
+        Self {
+            note_number,
+            alternatives: Vec::new(),
+            kind: NoteKind::Normal,
+            lyric: None,
+            detection: Some(detection),
+            index: None,
+        }
+    }
+
+    /// Distance in semitones from `note_number` to the closest of the acceptable note numbers.
+    pub fn distance(&self, note_number: f64) -> f64 {
+        self.deviation(note_number).abs()
+    }
+
+    /// Semitones that `note_number` is above the closest of the acceptable note numbers.
+    pub fn deviation(&self, note_number: f64) -> f64 {
+        self.alternatives.iter().map(|n| note_number - n).fold(
+            note_number - self.note_number,
+            |deviation, d| {
+                if d.abs() < deviation.abs() {
+                    d
+                } else {
+                    deviation
+                }
+            },
+        )
+    }
+
+    /// Confidence of the note, where notes that were not detected are fully confident.
+    pub fn clarity(&self) -> f64 {
+        self.detection.map_or(1., |d| d.clarity)
     }
 }
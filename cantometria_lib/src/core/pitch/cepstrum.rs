@@ -0,0 +1,78 @@
+use rustfft::{FftPlanner, num_complex::Complex};
+
+use crate::core::{WindowFunction, f64_to_usize, usize_to_f64};
+
+use super::{Pitch, PitchEstimator, parabolic_peak, square_sum};
+
+/// Estimates pitch from the peak of the real cepstrum, whose quefrency is the fundamental period.
+///
+/// Like [`super::HarmonicProductSpectrum`], it needs windows spanning several periods.
+pub struct Cepstrum {
+    pub min_frequency: f64,
+    pub max_frequency: f64,
+    pub power_threshold: f64,
+    /// Minimum prominence of the cepstral peak over the searched quefrencies.
+    pub clarity_threshold: f64,
+    planner: FftPlanner<f64>,
+}
+
+impl Default for Cepstrum {
+    fn default() -> Self {
+        Self {
+            min_frequency: 60.,
+            max_frequency: 1000.,
+            power_threshold: 1.0,
+            clarity_threshold: 0.3,
+            planner: FftPlanner::new(),
+        }
+    }
+}
+
+impl PitchEstimator for Cepstrum {
+    fn estimate(&mut self, frame: &[f64], sample_rate: usize) -> Option<Pitch> {
+        if square_sum(frame) < self.power_threshold {
+            return None;
+        }
+        let n = frame.len();
+        let rate = usize_to_f64(sample_rate);
+
+        let coefficients = WindowFunction::Hann.coefficients(n);
+        let mut buffer = frame
+            .iter()
+            .zip(&coefficients)
+            .map(|(s, c)| Complex::new(s * c, 0.))
+            .collect::<Box<_>>();
+        self.planner.plan_fft_forward(n).process(&mut buffer);
+        // the log spectrum is floored 80 dB below its peak so that near-silent bins do not drown
+        // out the harmonic ripple
+        let floor = buffer.iter().map(|c| c.norm()).fold(0., f64::max) * 1e-4;
+        for bin in &mut buffer {
+            *bin = Complex::new(bin.norm().max(floor).max(f64::MIN_POSITIVE).ln(), 0.);
+        }
+        self.planner.plan_fft_inverse(n).process(&mut buffer);
+        let cepstrum = buffer
+            .iter()
+            .map(|c| c.re / usize_to_f64(n))
+            .collect::<Box<_>>();
+
+        let shortest = f64_to_usize((rate / self.max_frequency).floor()).max(2);
+        let longest =
+            f64_to_usize((rate / self.min_frequency).ceil()).min((n / 2).saturating_sub(1));
+        let peak = (shortest..=longest).max_by(|&a, &b| cepstrum[a].total_cmp(&cepstrum[b]))?;
+        let quefrency = parabolic_peak(&cepstrum, peak);
+
+        let searched = &cepstrum[shortest..=longest];
+        let len = usize_to_f64(searched.len());
+        let mean = searched.iter().sum::<f64>() / len;
+        let deviation = (searched.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / len).sqrt();
+        // the largest of `len` samples of noise is expected about `sqrt(2 ln len)` deviations above
+        // the mean, so only the prominence beyond that counts towards the clarity
+        let prominence = (cepstrum[peak] - mean) / deviation;
+        let clarity = (1. - (2. * len.ln()).sqrt() / prominence).clamp(0., 1.);
+
+        (clarity >= self.clarity_threshold).then_some(Pitch {
+            frequency: rate / quefrency,
+            clarity,
+        })
+    }
+}
@@ -0,0 +1,121 @@
+use rustfft::{FftPlanner, num_complex::Complex};
+
+use crate::core::{WindowFunction, f64_to_usize, usize_to_f64};
+
+use super::{Pitch, PitchEstimator, parabolic_peak, square_sum};
+
+/// Estimates pitch as the peak of the product of the magnitude spectrum and its downsampled copies,
+/// where every harmonic of the fundamental lines up.
+///
+/// Harmonics are only resolved when the frame spans several periods, so low voices need windows of
+/// about 46 ms or longer.
+pub struct HarmonicProductSpectrum {
+    /// Number of spectra multiplied together, including the original.
+    pub harmonics: usize,
+    pub min_frequency: f64,
+    pub max_frequency: f64,
+    pub power_threshold: f64,
+    /// Minimum share of the spectral energy lying on the harmonics of the estimate.
+    pub clarity_threshold: f64,
+    planner: FftPlanner<f64>,
+}
+
+impl Default for HarmonicProductSpectrum {
+    fn default() -> Self {
+        Self {
+            harmonics: 4,
+            min_frequency: 60.,
+            max_frequency: 1500.,
+            power_threshold: 1.0,
+            clarity_threshold: 0.6,
+            planner: FftPlanner::new(),
+        }
+    }
+}
+
+impl HarmonicProductSpectrum {
+    /// Factor by which frames are zero-padded to interpolate the spectrum.
+    const ZERO_PADDING: usize = 4;
+}
+
+impl PitchEstimator for HarmonicProductSpectrum {
+    fn estimate(&mut self, frame: &[f64], sample_rate: usize) -> Option<Pitch> {
+        if square_sum(frame) < self.power_threshold {
+            return None;
+        }
+        let n = frame.len() * Self::ZERO_PADDING;
+        let bin_width = usize_to_f64(sample_rate) / usize_to_f64(n);
+
+        let coefficients = WindowFunction::Hann.coefficients(frame.len());
+        let mut buffer = frame
+            .iter()
+            .zip(&coefficients)
+            .map(|(s, c)| Complex::new(s * c, 0.))
+            .chain(std::iter::repeat(Complex::default()))
+            .take(n)
+            .collect::<Box<_>>();
+        self.planner.plan_fft_forward(n).process(&mut buffer);
+        let magnitudes = buffer[..n / 2].iter().map(|c| c.norm()).collect::<Box<_>>();
+
+        let len = magnitudes.len() / self.harmonics.max(1);
+        let product = (0..len)
+            .map(|k| {
+                (1..=self.harmonics)
+                    .map(|h| magnitudes[k * h])
+                    .product::<f64>()
+            })
+            .collect::<Box<_>>();
+
+        let lowest = f64_to_usize((self.min_frequency / bin_width).ceil()).max(1);
+        let highest =
+            f64_to_usize((self.max_frequency / bin_width).floor()).min(len.saturating_sub(1));
+        let peak = (lowest..=highest).max_by(|&a, &b| product[a].total_cmp(&product[b]))?;
+        let log_product = product
+            .iter()
+            .map(|p| p.max(f64::MIN_POSITIVE).ln())
+            .collect::<Box<_>>();
+
+        // the product peaks just as well an octave or more below a strongly voiced fundamental, so
+        // every multiple of the peak is considered and the one best explaining the spectrum is kept
+        let (multiple, clarity) = (1..=self.harmonics)
+            .take_while(|m| peak * m <= highest)
+            .map(|m| {
+                (
+                    m,
+                    harmonic_clarity(&magnitudes, peak * m, Self::ZERO_PADDING),
+                )
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        let frequency = parabolic_peak(&log_product, peak) * usize_to_f64(multiple) * bin_width;
+
+        (clarity >= self.clarity_threshold).then_some(Pitch { frequency, clarity })
+    }
+}
+
+/// Share of the spectral energy lying within `lobe` bins of the harmonics of `fundamental`, beyond
+/// the share that the same bins would hold in a flat spectrum.
+fn harmonic_clarity(magnitudes: &[f64], fundamental: usize, lobe: usize) -> f64 {
+    let mut on_harmonic = vec![false; magnitudes.len()];
+    for centre in (fundamental..magnitudes.len()).step_by(fundamental) {
+        let range = centre.saturating_sub(lobe)..(centre + lobe + 1).min(magnitudes.len());
+        on_harmonic[range].fill(true);
+    }
+    let coverage =
+        usize_to_f64(on_harmonic.iter().filter(|&&on| on).count()) / usize_to_f64(magnitudes.len());
+    // harmonics packed this closely are not resolved by the window, so they explain nothing
+    if coverage > 0.5 {
+        return 0.;
+    }
+    let (harmonic_energy, total_energy) =
+        magnitudes
+            .iter()
+            .zip(on_harmonic)
+            .fold((0., 0.), |(harmonic, total), (m, on)| {
+                let energy = m * m;
+                (
+                    if on { harmonic + energy } else { harmonic },
+                    total + energy,
+                )
+            });
+    ((harmonic_energy / total_energy - coverage) / (1. - coverage)).max(0.)
+}
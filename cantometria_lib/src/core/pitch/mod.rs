@@ -0,0 +1,296 @@
+mod cepstrum;
+mod hps;
+mod pyin;
+
+use std::borrow::Cow;
+
+use pitch_detection::detector::{PitchDetector, mcleod::McLeodDetector, yin::YINDetector};
+
+pub use cepstrum::Cepstrum;
+pub use hps::HarmonicProductSpectrum;
+pub use pyin::PYin;
+
+/// A pitch estimate of a single analysis frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pitch {
+    pub frequency: f64,
+    /// Confidence of the estimate in `[0.0, 1.0]`.
+    pub clarity: f64,
+}
+
+/// An algorithm that estimates the fundamental frequency of an analysis frame.
+pub trait PitchEstimator {
+    /// Returns the pitch of `frame`, or `None` if the frame is too quiet or not clearly pitched.
+    fn estimate(&mut self, frame: &[f64], sample_rate: usize) -> Option<Pitch>;
+
+    /// Returns the pitch of every frame of a recording in order.
+    ///
+    /// Frames are estimated independently by default, while estimators that smooth over the whole
+    /// recording override this.
+    fn track<I>(&mut self, frames: I, sample_rate: usize) -> Vec<Option<Pitch>>
+    where
+        I: IntoIterator<Item = Vec<f64>>,
+        Self: Sized,
+    {
+        frames
+            .into_iter()
+            .map(|frame| self.estimate(&frame, sample_rate))
+            .collect()
+    }
+}
+
+fn square_sum(frame: &[f64]) -> f64 {
+    frame.iter().map(|s| s * s).sum()
+}
+
+/// Refines the position of the extremum at `i` by fitting a parabola through its neighbours.
+fn parabolic_peak(values: &[f64], i: usize) -> f64 {
+    let (Some(&left), Some(&centre), Some(&right)) = (
+        i.checked_sub(1).and_then(|j| values.get(j)),
+        values.get(i),
+        values.get(i + 1),
+    ) else {
+        return crate::core::usize_to_f64(i);
+    };
+    let denominator = 2.0f64.mul_add(-centre, left + right);
+    let offset = if denominator.abs() > f64::EPSILON {
+        0.5 * (left - right) / denominator
+    } else {
+        0.
+    };
+    crate::core::usize_to_f64(i) + offset
+}
+
+/// Fits `frame` to the nearest power of two samples, which the detectors of the `pitch-detection`
+/// crate need, by keeping its centre or by zero-padding its end so that the shorter lags still
+/// compare samples of the frame only.
+fn power_of_two_frame(frame: &[f64]) -> Cow<'_, [f64]> {
+    let n = frame.len();
+    let up = n.next_power_of_two();
+    if n == up {
+        return Cow::Borrowed(frame);
+    }
+    if n * n < up * (up / 2) {
+        let start = (n - up / 2) / 2;
+        return Cow::Borrowed(&frame[start..start + up / 2]);
+    }
+    let mut padded = frame.to_vec();
+    padded.resize(up, 0.);
+    Cow::Owned(padded)
+}
+
+/// Keeps a detector from the `pitch-detection` crate sized to the frames it is given.
+struct Resizing<D> {
+    size: usize,
+    detector: D,
+}
+
+impl<D> Resizing<D> {
+    fn get(slot: &mut Option<Self>, size: usize, new: impl FnOnce(usize, usize) -> D) -> &mut D {
+        if slot.as_ref().is_some_and(|s| s.size != size) {
+            *slot = None;
+        }
+        &mut slot
+            .get_or_insert_with(|| Self {
+                size,
+                detector: new(size, size / 2),
+            })
+            .detector
+    }
+}
+
+/// The YIN estimator, based on the cumulative mean normalised difference function.
+pub struct Yin {
+    pub power_threshold: f64,
+    pub clarity_threshold: f64,
+    detector: Option<Resizing<YINDetector<f64>>>,
+}
+
+impl Default for Yin {
+    fn default() -> Self {
+        Self {
+            power_threshold: 1.0,
+            clarity_threshold: 0.9,
+            detector: None,
+        }
+    }
+}
+
+impl PitchEstimator for Yin {
+    fn estimate(&mut self, frame: &[f64], sample_rate: usize) -> Option<Pitch> {
+        let frame = power_of_two_frame(frame);
+        let pitch = Resizing::get(&mut self.detector, frame.len(), YINDetector::new).get_pitch(
+            &frame,
+            sample_rate,
+            self.power_threshold,
+            self.clarity_threshold,
+        )?;
+        // `pitch-detection` reports the YIN clarity rescaled around the threshold, so the
+        // aperiodicity `d'(τ)` it was derived from is recovered first
+        let threshold = 1. - self.clarity_threshold;
+        let peak = (pitch.clarity - self.clarity_threshold) * threshold;
+        let aperiodicity = peak.mul_add(1. - threshold, threshold);
+        Some(Pitch {
+            frequency: pitch.frequency,
+            clarity: (1. - aperiodicity).clamp(0., 1.),
+        })
+    }
+}
+
+/// The `McLeod` pitch method, based on the normalised square difference function.
+pub struct McLeod {
+    pub power_threshold: f64,
+    pub clarity_threshold: f64,
+    detector: Option<Resizing<McLeodDetector<f64>>>,
+}
+
+impl Default for McLeod {
+    fn default() -> Self {
+        Self {
+            power_threshold: 1.0,
+            clarity_threshold: 0.7,
+            detector: None,
+        }
+    }
+}
+
+impl PitchEstimator for McLeod {
+    fn estimate(&mut self, frame: &[f64], sample_rate: usize) -> Option<Pitch> {
+        let frame = power_of_two_frame(frame);
+        let pitch = Resizing::get(&mut self.detector, frame.len(), McLeodDetector::new).get_pitch(
+            &frame,
+            sample_rate,
+            self.power_threshold,
+            self.clarity_threshold,
+        )?;
+        Some(Pitch {
+            frequency: pitch.frequency,
+            clarity: pitch.clarity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{
+        Cepstrum, HarmonicProductSpectrum, McLeod, PYin, PitchEstimator, Yin, power_of_two_frame,
+    };
+    use crate::core::usize_to_f64;
+
+    const SAMPLE_RATE: usize = 44_100;
+    const SIZE: usize = 2048;
+
+    /// A sawtooth-like tone with decaying harmonics, closer to a voice than a pure sine.
+    fn tone(frequency: f64, len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|n| {
+                let t = usize_to_f64(n) / usize_to_f64(SAMPLE_RATE);
+                (1..=20)
+                    .map(|h| {
+                        let h = f64::from(h);
+                        (std::f64::consts::TAU * frequency * h * t).sin() / h
+                    })
+                    .sum::<f64>()
+                    * 0.3
+            })
+            .collect()
+    }
+
+    /// Uniform white noise from a linear congruential generator.
+    fn noise(len: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_u64;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                usize_to_f64(usize::try_from(state >> 40).unwrap_or_default())
+                    / usize_to_f64(1 << 24)
+                    - 0.5
+            })
+            .collect()
+    }
+
+    #[rstest]
+    fn estimate(
+        #[values(
+            Box::new(Yin::default()) as Box<dyn PitchEstimator>,
+            Box::new(PYin::default()),
+            Box::new(McLeod::default()),
+            Box::new(HarmonicProductSpectrum::default()),
+            Box::new(Cepstrum::default())
+        )]
+        mut estimator: Box<dyn PitchEstimator>,
+        #[values(110., 220., 440.)] frequency: f64,
+    ) {
+        let pitch = estimator
+            .estimate(&tone(frequency, SIZE), SAMPLE_RATE)
+            .expect("no pitch estimated");
+        assert!((pitch.frequency / frequency - 1.).abs() < 0.01);
+        assert!((0.0..=1.0).contains(&pitch.clarity));
+        assert!(estimator.estimate(&[0.; SIZE], SAMPLE_RATE).is_none());
+        assert!(estimator.estimate(&noise(SIZE), SAMPLE_RATE).is_none());
+    }
+
+    #[rstest]
+    #[case(&[1., 2., 3., 4.], &[1., 2., 3., 4.])]
+    #[case(&[1., 2., 3.], &[1., 2., 3., 0.])]
+    #[case(&[1., 2., 3., 4., 5.], &[1., 2., 3., 4.])]
+    #[case(&[1., 2., 3., 4., 5., 6.], &[1., 2., 3., 4., 5., 6., 0., 0.])]
+    #[case(&[1., 2., 3., 4., 5., 6., 7., 8., 9., 10.], &[2., 3., 4., 5., 6., 7., 8., 9.])]
+    fn power_of_two_frames(#[case] frame: &[f64], #[case] expected: &[f64]) {
+        assert_eq!(&*power_of_two_frame(frame), expected);
+    }
+
+    #[rstest]
+    fn any_frame_length(
+        #[values(
+            Box::new(Yin::default()) as Box<dyn PitchEstimator>,
+            Box::new(PYin::default()),
+            Box::new(McLeod::default()),
+            Box::new(HarmonicProductSpectrum::default()),
+            Box::new(Cepstrum::default())
+        )]
+        mut estimator: Box<dyn PitchEstimator>,
+        #[values(1014, 1104, 1543)] len: usize,
+    ) {
+        let pitch = estimator
+            .estimate(&tone(220., len), SAMPLE_RATE)
+            .expect("no pitch estimated");
+        assert!((pitch.frequency / 220. - 1.).abs() < 0.01);
+    }
+
+    #[test]
+    fn smoothed_voicing() {
+        const HOP: usize = 256;
+        const HALF_SECOND: usize = SAMPLE_RATE / 2;
+        let breathy = tone(220., HALF_SECOND)
+            .iter()
+            .zip(noise(HALF_SECOND))
+            .map(|(t, n)| 0.3f64.mul_add(n, *t))
+            .collect::<Vec<_>>();
+        let signal = [&breathy[..], &vec![0.; HALF_SECOND], &breathy].concat();
+        let frames =
+            || (0..(signal.len() - SIZE) / HOP).map(|i| signal[i * HOP..i * HOP + SIZE].to_vec());
+        let region = |i: usize| {
+            let (start, end) = (i * HOP, i * HOP + SIZE);
+            match (start / HALF_SECOND, (end - 1) / HALF_SECOND) {
+                (0, 0) | (2, 2) => Some(true),
+                (1, 1) => Some(false),
+                _ => None,
+            }
+        };
+        let correct = |pitches: &[Option<super::Pitch>]| {
+            pitches.iter().enumerate().all(|(i, p)| match region(i) {
+                Some(true) => p.is_some_and(|p| (p.frequency / 220. - 1.).abs() < 0.01),
+                Some(false) => p.is_none(),
+                None => true,
+            })
+        };
+
+        assert!(!correct(&Yin::default().track(frames(), SAMPLE_RATE)));
+        assert!(correct(&PYin::default().track(frames(), SAMPLE_RATE)));
+    }
+}
@@ -0,0 +1,282 @@
+use rustfft::{FftPlanner, num_complex::Complex};
+
+use crate::core::{f64_to_usize, usize_to_f64};
+
+use super::{Pitch, PitchEstimator, parabolic_peak, square_sum};
+
+/// Probabilistic YIN with hidden Markov model smoothing of the pitch track.
+///
+/// Every trough of the cumulative mean normalised difference function is weighed by how many
+/// plausible thresholds would select it, then the most likely voiced/unvoiced pitch path through
+/// the whole recording is decoded.
+pub struct PYin {
+    pub min_frequency: f64,
+    pub max_frequency: f64,
+    pub power_threshold: f64,
+    /// Probability of a frame keeping the voicing of the previous frame.
+    pub voicing_persistence: f64,
+    /// Largest pitch change between consecutive frames in semitones.
+    pub max_step: f64,
+    planner: FftPlanner<f64>,
+}
+
+impl Default for PYin {
+    fn default() -> Self {
+        Self {
+            min_frequency: 55.,
+            max_frequency: 1760.,
+            power_threshold: 1.0,
+            voicing_persistence: 0.99,
+            max_step: 3.,
+            planner: FftPlanner::new(),
+        }
+    }
+}
+
+/// A candidate pitch of a frame with the probability of it being the voiced fundamental.
+struct Candidate {
+    frequency: f64,
+    probability: f64,
+}
+
+impl PYin {
+    /// Resolution of the hidden pitch states.
+    const BINS_PER_SEMITONE: f64 = 5.;
+    /// Number of thresholds the beta prior is spread over, evenly in `(0, 1]`.
+    const THRESHOLDS: usize = 100;
+    /// Parameters of the beta prior over thresholds, which has a mean of 0.1.
+    const BETA: (f64, f64) = (2., 18.);
+    /// Weight given to the global minimum when no trough falls below a threshold.
+    const ABSOLUTE_MIN_WEIGHT: f64 = 0.01;
+
+    fn threshold_prior() -> impl Iterator<Item = (f64, f64)> {
+        let (alpha, beta) = Self::BETA;
+        let thresholds =
+            (1..=Self::THRESHOLDS).map(|i| usize_to_f64(i) / usize_to_f64(Self::THRESHOLDS));
+        let density = move |s: f64| s.powf(alpha - 1.) * (1. - s).powf(beta - 1.);
+        let total = thresholds.clone().map(density).sum::<f64>();
+        thresholds.map(move |s| (s, density(s) / total))
+    }
+
+    /// Computes the cumulative mean normalised difference function `d'(τ)` for lags up to half
+    /// of the frame, using an FFT for the cross term.
+    fn normalised_difference(&mut self, frame: &[f64]) -> Box<[f64]> {
+        let window = frame.len() / 2;
+        let size = (frame.len() + window).next_power_of_two();
+        let mut whole = frame
+            .iter()
+            .map(|&s| Complex::new(s, 0.))
+            .chain(std::iter::repeat(Complex::default()))
+            .take(size)
+            .collect::<Box<_>>();
+        let mut head = frame[..window]
+            .iter()
+            .map(|&s| Complex::new(s, 0.))
+            .chain(std::iter::repeat(Complex::default()))
+            .take(size)
+            .collect::<Box<_>>();
+        let fft = self.planner.plan_fft_forward(size);
+        fft.process(&mut whole);
+        fft.process(&mut head);
+        for (w, h) in whole.iter_mut().zip(&head) {
+            *w *= h.conj();
+        }
+        self.planner.plan_fft_inverse(size).process(&mut whole);
+
+        let mut energy = Vec::with_capacity(frame.len() + 1);
+        energy.push(0.);
+        for s in frame {
+            energy.push(energy[energy.len() - 1] + s * s);
+        }
+        let scale = usize_to_f64(size);
+        let mut cumulative = 0.;
+        (0..window)
+            .map(|lag| {
+                if lag == 0 {
+                    return 1.;
+                }
+                let difference = energy[window] + energy[lag + window]
+                    - energy[lag]
+                    - 2. * whole[lag].re / scale;
+                cumulative += difference;
+                if cumulative > 0. {
+                    difference * usize_to_f64(lag) / cumulative
+                } else {
+                    1.
+                }
+            })
+            .collect()
+    }
+
+    fn candidates(&mut self, frame: &[f64], sample_rate: usize) -> Vec<Candidate> {
+        if square_sum(frame) < self.power_threshold {
+            return Vec::new();
+        }
+        let rate = usize_to_f64(sample_rate);
+        let difference = self.normalised_difference(frame);
+        let shortest = f64_to_usize((rate / self.max_frequency).floor()).max(2);
+        let longest = f64_to_usize((rate / self.min_frequency).ceil())
+            .min(difference.len().saturating_sub(2));
+        if longest < shortest {
+            return Vec::new();
+        }
+
+        let troughs = (shortest..=longest)
+            .filter(|&lag| {
+                difference[lag] < difference[lag - 1] && difference[lag] <= difference[lag + 1]
+            })
+            .collect::<Box<_>>();
+        let Some(global) = (0..troughs.len())
+            .min_by(|&a, &b| difference[troughs[a]].total_cmp(&difference[troughs[b]]))
+        else {
+            return Vec::new();
+        };
+
+        let mut probabilities = vec![0.; troughs.len()];
+        for (threshold, weight) in Self::threshold_prior() {
+            if let Some(i) = troughs.iter().position(|&lag| difference[lag] < threshold) {
+                probabilities[i] += weight;
+            } else {
+                probabilities[global] += weight * Self::ABSOLUTE_MIN_WEIGHT;
+            }
+        }
+
+        troughs
+            .iter()
+            .zip(probabilities)
+            .filter(|&(_, probability)| probability > 0.)
+            .map(|(&lag, probability)| Candidate {
+                frequency: rate / parabolic_peak(&difference, lag),
+                probability,
+            })
+            .collect()
+    }
+
+    fn bins(&self) -> usize {
+        f64_to_usize(
+            (12. * Self::BINS_PER_SEMITONE * (self.max_frequency / self.min_frequency).log2())
+                .ceil(),
+        ) + 1
+    }
+
+    fn bin(&self, frequency: f64) -> Option<usize> {
+        let bin = 12. * Self::BINS_PER_SEMITONE * (frequency / self.min_frequency).log2();
+        (bin > -0.5)
+            .then(|| f64_to_usize(bin.round()))
+            .filter(|&b| b < self.bins())
+    }
+
+    /// Finds the most likely sequence of voiced pitch bins, or `None` for unvoiced frames.
+    fn decode(&self, observations: &[Vec<Candidate>]) -> Vec<Option<usize>> {
+        let bins = self.bins();
+        let reach = f64_to_usize((self.max_step * Self::BINS_PER_SEMITONE).round());
+        let kernel = (0..=reach)
+            .map(|d| usize_to_f64(reach + 1 - d))
+            .collect::<Box<_>>();
+        let kernel_total = 2.0f64.mul_add(kernel[1..].iter().sum(), kernel[0]);
+        let log_kernel = kernel
+            .iter()
+            .map(|k| (k / kernel_total).ln())
+            .collect::<Box<_>>();
+        let log_stay = self.voicing_persistence.ln();
+        let log_switch = (1. - self.voicing_persistence).max(f64::MIN_POSITIVE).ln();
+        let log = |p: f64| p.max(f64::MIN_POSITIVE).ln();
+
+        // states `0..bins` are voiced and `bins..2 * bins` unvoiced at the same pitch
+        let mut delta = vec![0.; 2 * bins];
+        let mut backpointers = Vec::with_capacity(observations.len());
+        let mut emission = vec![0.; 2 * bins];
+        for candidates in observations {
+            emission[..bins].fill(0.);
+            let mut voiced = 0.;
+            for candidate in candidates {
+                if let Some(b) = self.bin(candidate.frequency) {
+                    emission[b] += candidate.probability;
+                    voiced += candidate.probability;
+                }
+            }
+            emission[bins..].fill((1. - voiced).max(0.) / usize_to_f64(bins));
+
+            let mut next = vec![f64::NEG_INFINITY; 2 * bins];
+            let mut pointers = vec![0u32; 2 * bins];
+            for state in 0..2 * bins {
+                let (b, is_voiced) = (state % bins, state < bins);
+                for source_bin in b.saturating_sub(reach)..(b + reach + 1).min(bins) {
+                    let transition = log_kernel[source_bin.abs_diff(b)];
+                    for source in [source_bin, source_bin + bins] {
+                        let voicing = if (source < bins) == is_voiced {
+                            log_stay
+                        } else {
+                            log_switch
+                        };
+                        let score = delta[source] + transition + voicing;
+                        if score > next[state] {
+                            next[state] = score;
+                            pointers[state] = u32::try_from(source).unwrap_or_default();
+                        }
+                    }
+                }
+                next[state] += log(emission[state]);
+            }
+            delta = next;
+            backpointers.push(pointers);
+        }
+
+        let Some(mut state) = (0..2 * bins).max_by(|&a, &b| delta[a].total_cmp(&delta[b])) else {
+            return Vec::new();
+        };
+        let mut path = vec![None; observations.len()];
+        for (t, pointers) in backpointers.iter().enumerate().rev() {
+            path[t] = (state < bins).then_some(state);
+            state = pointers[state] as usize;
+        }
+        path
+    }
+}
+
+impl PitchEstimator for PYin {
+    /// Estimates a single frame without smoothing, taking the most probable candidate when the
+    /// frame is more likely voiced than not.
+    fn estimate(&mut self, frame: &[f64], sample_rate: usize) -> Option<Pitch> {
+        let candidates = self.candidates(frame, sample_rate);
+        let voiced = candidates.iter().map(|c| c.probability).sum::<f64>();
+        let best = candidates
+            .into_iter()
+            .max_by(|a, b| a.probability.total_cmp(&b.probability))?;
+        (voiced >= 0.5).then_some(Pitch {
+            frequency: best.frequency,
+            clarity: voiced,
+        })
+    }
+
+    fn track<I>(&mut self, frames: I, sample_rate: usize) -> Vec<Option<Pitch>>
+    where
+        I: IntoIterator<Item = Vec<f64>>,
+    {
+        let observations = frames
+            .into_iter()
+            .map(|frame| self.candidates(&frame, sample_rate))
+            .collect::<Box<_>>();
+        let path = self.decode(&observations);
+        observations
+            .iter()
+            .zip(path)
+            .map(|(candidates, bin)| {
+                let bin = bin?;
+                let clarity = candidates.iter().map(|c| c.probability).sum::<f64>();
+                let frequency = candidates
+                    .iter()
+                    .filter(|c| self.bin(c.frequency) == Some(bin))
+                    .max_by(|a, b| a.probability.total_cmp(&b.probability))
+                    .map_or_else(
+                        || {
+                            self.min_frequency
+                                * (usize_to_f64(bin) / (12. * Self::BINS_PER_SEMITONE)).exp2()
+                        },
+                        |c| c.frequency,
+                    );
+                Some(Pitch { frequency, clarity })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,283 @@
+use std::path::Path;
+
+use crate::{
+    core::{isize_to_f64, usize_to_isize},
+    error::{OpenTuningError, ParseScalaError},
+};
+
+/// Maps MIDI note numbers to frequencies, and frequencies to continuous note numbers.
+///
+/// Continuous note numbers count equal-tempered semitones from the reference note at the reference
+/// frequency, so that the note numbers of both the target and the singing are measured on the same
+/// scale and their differences are true intervals in any temperament.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    /// Cents of every scale degree from the first above the tonic up to the period.
+    scale: Box<[f64]>,
+    /// Scale degree of each key in a repeating keyboard pattern, `None` for unmapped keys.
+    mapping: Box<[Option<usize>]>,
+    /// First note of the keyboard mapping, where the tonic lies.
+    middle_note: u8,
+    reference_note: u8,
+    reference_frequency: f64,
+    /// Number of scale degrees the keyboard pattern moves up by each time it repeats.
+    octave_degree: usize,
+}
+
+impl Default for Tuning {
+    /// Twelve-tone equal temperament with A4 at 440 Hz.
+    fn default() -> Self {
+        Self::equal(69, 440.)
+    }
+}
+
+/// Parses the next line of a Scala file which is not a comment.
+fn next_line<'a>(
+    lines: &mut impl Iterator<Item = &'a str>,
+    what: &'static str,
+) -> Result<&'a str, ParseScalaError> {
+    lines
+        .find(|line| !line.starts_with('!'))
+        .map(str::trim)
+        .ok_or(ParseScalaError::Missing(what))
+}
+
+fn parse_number<T: std::str::FromStr>(line: &str) -> Result<T, ParseScalaError> {
+    let word = line.split_whitespace().next().unwrap_or_default();
+    word.parse()
+        .map_err(|_| ParseScalaError::InvalidNumber(line.to_owned()))
+}
+
+/// Parses a Scala pitch, which is in cents if it contains a period and a ratio otherwise.
+fn parse_pitch(line: &str) -> Result<f64, ParseScalaError> {
+    let word = line.split_whitespace().next().unwrap_or_default();
+    let invalid = || ParseScalaError::InvalidPitch(line.to_owned());
+    if word.contains('.') {
+        return word.parse().map_err(|_| invalid());
+    }
+    let (numerator, denominator) = word.split_once('/').unwrap_or((word, "1"));
+    let numerator = numerator.parse::<u64>().map_err(|_| invalid())?;
+    let denominator = denominator.parse::<u64>().map_err(|_| invalid())?;
+    if numerator == 0 || denominator == 0 {
+        return Err(invalid());
+    }
+    #[allow(clippy::cast_precision_loss)]
+    Ok(1200. * (numerator as f64 / denominator as f64).log2())
+}
+
+impl Tuning {
+    /// Twelve-tone equal temperament with `reference_note` at `reference_frequency`.
+    #[must_use]
+    pub fn equal(reference_note: u8, reference_frequency: f64) -> Self {
+        Self {
+            scale: (1..=12).map(|d| f64::from(d) * 100.).collect(),
+            mapping: Box::new([]),
+            middle_note: 60,
+            reference_note,
+            reference_frequency,
+            octave_degree: 12,
+        }
+    }
+
+    /// Builds a tuning from the contents of a Scala scale (`.scl`) file and optionally a Scala
+    /// keyboard mapping (`.kbm`) file.
+    ///
+    /// Without a keyboard mapping, consecutive notes step through consecutive scale degrees from
+    /// middle C, with A4 tuned to 440 Hz.
+    ///
+    /// # Errors
+    /// - either file is malformed
+    pub fn from_scala(scl: &str, kbm: Option<&str>) -> Result<Self, ParseScalaError> {
+        let mut lines = scl.lines();
+        next_line(&mut lines, "scale description")?;
+        let len = parse_number::<usize>(next_line(&mut lines, "number of notes")?)?;
+        let scale = (0..len)
+            .map(|_| parse_pitch(next_line(&mut lines, "scale pitch")?))
+            .collect::<Result<Box<_>, _>>()?;
+        if scale.is_empty() {
+            return Err(ParseScalaError::Missing("scale pitch"));
+        }
+
+        let Some(kbm) = kbm else {
+            let tuning = Self {
+                octave_degree: scale.len(),
+                scale,
+                mapping: Box::new([]),
+                middle_note: 60,
+                reference_note: 69,
+                reference_frequency: 440.,
+            };
+            return Ok(tuning);
+        };
+
+        let mut lines = kbm.lines();
+        let size = parse_number::<usize>(next_line(&mut lines, "map size")?)?;
+        next_line(&mut lines, "first note")?;
+        next_line(&mut lines, "last note")?;
+        let middle_note = parse_number(next_line(&mut lines, "middle note")?)?;
+        let reference_note = parse_number(next_line(&mut lines, "reference note")?)?;
+        let reference_frequency = parse_number(next_line(&mut lines, "reference frequency")?)?;
+        let octave_degree = parse_number(next_line(&mut lines, "octave degree")?)?;
+        let mapping = (0..size)
+            .map(|_| {
+                let line = next_line(&mut lines, "key mapping").unwrap_or("x");
+                if line.starts_with('x') {
+                    Ok(None)
+                } else {
+                    parse_number(line).map(Some)
+                }
+            })
+            .collect::<Result<Box<_>, _>>()?;
+        Ok(Self {
+            scale,
+            mapping,
+            middle_note,
+            reference_note,
+            reference_frequency,
+            octave_degree,
+        })
+    }
+
+    /// Reads a tuning from Scala scale and keyboard mapping files.
+    ///
+    /// # Errors
+    /// - opening either file failed
+    /// - either file is malformed
+    pub fn open_scala<P: AsRef<Path>>(scl: P, kbm: Option<P>) -> Result<Self, OpenTuningError> {
+        let scl = std::fs::read_to_string(scl)?;
+        let kbm = kbm.map(std::fs::read_to_string).transpose()?;
+        Ok(Self::from_scala(&scl, kbm.as_deref())?)
+    }
+
+    /// Scale degree of `note` counted from the tonic at the middle note, if the key is mapped.
+    fn degree(&self, note: u8) -> Option<isize> {
+        let offset = isize::from(note) - isize::from(self.middle_note);
+        if self.mapping.is_empty() {
+            return Some(offset);
+        }
+        let size = usize_to_isize(self.mapping.len());
+        let degree = self.mapping[offset.rem_euclid(size).unsigned_abs()]?;
+        Some(usize_to_isize(degree) + offset.div_euclid(size) * usize_to_isize(self.octave_degree))
+    }
+
+    /// Cents of a scale degree above the tonic.
+    fn cents(&self, degree: isize) -> f64 {
+        let len = usize_to_isize(self.scale.len());
+        let period = self.scale[self.scale.len() - 1];
+        let within = degree.rem_euclid(len).unsigned_abs();
+        let step = if within == 0 {
+            0.
+        } else {
+            self.scale[within - 1]
+        };
+        isize_to_f64(degree.div_euclid(len)).mul_add(period, step)
+    }
+
+    /// Frequency of `note` in hertz, or `None` if the keyboard mapping leaves it unmapped.
+    #[must_use]
+    pub fn note_frequency(&self, note: u8) -> Option<f64> {
+        let cents = self.cents(self.degree(note)?) - self.cents(self.degree(self.reference_note)?);
+        Some(self.reference_frequency * (cents / 1200.).exp2())
+    }
+
+    /// Continuous note number of `frequency`, or `None` if it is not positive.
+    #[must_use]
+    pub fn frequency_to_note_number(&self, frequency: f64) -> Option<f64> {
+        (frequency > 0.).then(|| {
+            12.0f64.mul_add(
+                (frequency / self.reference_frequency).log2(),
+                f64::from(self.reference_note),
+            )
+        })
+    }
+
+    /// Continuous note number that `note` sounds at in this tuning.
+    #[must_use]
+    pub fn note_to_note_number(&self, note: u8) -> Option<f64> {
+        self.frequency_to_note_number(self.note_frequency(note)?)
+    }
+
+    /// Cents by which `frequency` deviates from `note` as tuned.
+    #[must_use]
+    pub fn cents_from(&self, frequency: f64, note: u8) -> Option<f64> {
+        Some(100. * (self.frequency_to_note_number(frequency)? - self.note_to_note_number(note)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::Tuning;
+
+    const MEANTONE: &str = "! meanquar.scl
+!
+1/4-comma meantone scale. Pietro Aaron's temperament (1523)
+ 12
+!
+ 76.04900
+ 193.15686
+ 310.26471
+ 5/4
+ 503.42157
+ 579.47057
+ 696.57843
+ 25/16
+ 889.73529
+ 1006.84314
+ 1082.89214
+ 2/1
+";
+
+    const BAROQUE_PITCH: &str = "! A4 = 415 Hz, tonic on middle C
+12
+0
+127
+60
+69
+415.0
+12
+0
+1
+2
+3
+4
+5
+6
+7
+8
+9
+10
+11
+";
+
+    #[rstest]
+    #[case(Tuning::default(), 69, 440., 69.)]
+    #[case(Tuning::default(), 60, 261.625_565, 60.)]
+    #[case(Tuning::equal(69, 442.), 69, 442., 69.)]
+    #[case(Tuning::equal(69, 442.), 81, 884., 81.)]
+    #[case(Tuning::from_scala(MEANTONE, Some(BAROQUE_PITCH)).expect("parsing failed"), 69, 415., 69.)]
+    #[case(Tuning::from_scala(MEANTONE, Some(BAROQUE_PITCH)).expect("parsing failed"), 64, 310.284_871, 63.965_784)]
+    #[case(Tuning::from_scala(MEANTONE, None).expect("parsing failed"), 72, 526.362_770, 72.102_647)]
+    fn tune(
+        #[case] tuning: Tuning,
+        #[case] note: u8,
+        #[case] frequency: f64,
+        #[case] note_number: f64,
+    ) {
+        let tuned = tuning.note_frequency(note).expect("note unmapped");
+        assert!((tuned / frequency - 1.).abs() < 1e-5);
+        let tuned = tuning.note_to_note_number(note).expect("note unmapped");
+        assert!((tuned - note_number).abs() < 1e-5);
+        let cents = tuning.cents_from(frequency, note).expect("note unmapped");
+        assert!(cents.abs() < 1e-2);
+    }
+
+    #[test]
+    fn unmapped() {
+        let kbm = "2\n0\n127\n60\n60\n261.6\n12\n0\nx\n";
+        let tuning = Tuning::from_scala(MEANTONE, Some(kbm)).expect("parsing failed");
+        assert!(tuning.note_frequency(62).is_some());
+        assert!(tuning.note_frequency(63).is_none());
+    }
+}
@@ -18,17 +18,39 @@ use thiserror::Error;
 #[derive(Error, Debug)]
 #[error(transparent)]
 pub enum RunError {
-    OpenMidi(#[from] OpenMidiError),
+    OpenTarget(#[from] OpenTargetError),
     OpenWav(#[from] hound::Error),
     NewUnpaddedInputMelody(#[from] NewUnpaddedInputMelodyError),
-    NewRawUnpaddedInputMelody(#[from] NewRawUnpaddedTargetMelodyError),
     #[error("input melody is empty")]
     InputMelodyEmpty,
+    #[error("input melody is not sung on any note of the target melody")]
+    NoteOverlapEmpty,
+    #[error("tempo ratios {min_ratio}..={max_ratio} are not a positive range")]
+    InvalidTempoRange {
+        min_ratio: f64,
+        max_ratio: f64,
+    },
+    WriteMidi(#[from] midi_file::Error),
     #[cfg(feature = "visualise")]
     #[error("plotting failed")]
     Plot(#[from] PlotError),
 }
 
+#[derive(Error, Debug)]
+#[error(transparent)]
+pub enum OpenTargetError {
+    FileOpen(#[from] std::io::Error),
+    OpenMidi(#[from] OpenMidiError),
+    NewRawUnpaddedTargetMelody(#[from] NewRawUnpaddedTargetMelodyError),
+    ParseUltraStar(#[from] ParseUltraStarError),
+    ParseMusicXml(#[from] ParseMusicXmlError),
+    ReadMxl(#[from] zip::result::ZipError),
+    #[error("no score found in the compressed musicxml file")]
+    MxlScoreMissing,
+    #[error("unsupported target file extension: {:?}", .0)]
+    UnsupportedExtension(String),
+}
+
 #[derive(Error, Debug)]
 #[error(transparent)]
 pub enum OpenMidiError {
@@ -38,14 +60,71 @@ pub enum OpenMidiError {
 
 #[derive(Error, Debug)]
 pub enum NewRawUnpaddedTargetMelodyError {
-    #[error("midi file tracks len not equal to one: {}", .0)]
-    MidiFileTracksLenNotOne(u32),
-    #[error("unsupported time division type (SMPTE)")]
-    UnsupportedDivisionType,
+    #[error("no midi track matches the selection")]
+    TrackNotFound,
+    #[error("several midi tracks match the selection: {:?}", .0)]
+    TrackAmbiguous(Box<[u32]>),
+    #[error("note {0} is not mapped by the tuning")]
+    UnmappedNote(u8),
 }
 
 #[derive(Error, Debug)]
 pub enum NewUnpaddedInputMelodyError {
     #[error("error reading sample from WAV file")]
     WavSampleRead(#[from] hound::Error),
+    #[error("unsupported WAV sample format: {}-bit {:?}", .1, .0)]
+    UnsupportedSampleFormat(hound::SampleFormat, u16),
+    #[error("channel {channel} does not exist in a {channels}-channel recording")]
+    ChannelOutOfRange { channel: u16, channels: u16 },
+    #[error("analysis frames of {window_secs} s every {hop_secs} s are not positive durations")]
+    InvalidFrameDuration { window_secs: f64, hop_secs: f64 },
+}
+
+#[derive(Error, Debug)]
+pub enum ParseScalaError {
+    #[error("missing {0}")]
+    Missing(&'static str),
+    #[error("invalid pitch: {0}")]
+    InvalidPitch(String),
+    #[error("invalid number: {0}")]
+    InvalidNumber(String),
+}
+
+#[derive(Error, Debug)]
+#[error(transparent)]
+pub enum OpenTuningError {
+    FileOpen(#[from] std::io::Error),
+    ParseScala(#[from] ParseScalaError),
+}
+
+#[derive(Error, Debug)]
+pub enum ParseUltraStarError {
+    #[error("missing #BPM header before the first note")]
+    MissingBpm,
+    #[error("invalid #{} header: {:?}", .0, .1)]
+    InvalidHeader(String, String),
+    #[error("invalid note or line break on line {}", .0)]
+    InvalidLine(usize),
+    #[error("pitch {} is out of the midi range", .0)]
+    PitchOutOfRange(i32),
+    #[error("note {} is not mapped by the tuning", .0)]
+    UnmappedNote(u8),
+}
+
+#[derive(Error, Debug)]
+pub enum ParseMusicXmlError {
+    #[error("malformed xml")]
+    Xml(#[from] roxmltree::Error),
+    #[error("unsupported root element <{}>, expected <score-partwise>", .0)]
+    UnsupportedRoot(String),
+    #[error("no musicxml part matches the selection")]
+    PartNotFound,
+    #[error("several musicxml parts match the selection: {:?}", .0)]
+    PartAmbiguous(Box<[String]>),
+    #[error("invalid <{}> value: {:?}", .0, .1)]
+    InvalidValue(String, String),
+    #[error("pitch {} is out of the midi range", .0)]
+    PitchOutOfRange(i32),
+    #[error("note {} is not mapped by the tuning", .0)]
+    UnmappedNote(u8),
 }
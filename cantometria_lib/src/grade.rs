@@ -1,4 +1,14 @@
-use crate::core::{NoteSeries, median, usize_to_f64};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    sync::Arc,
+};
+
+use crate::align::KeyShift;
+use crate::core::{
+    Lyric, Note, NoteKind, NoteSeries, NoteTimeSeries, Position, f64_to_usize, isize_to_f64,
+    median, usize_to_f64, usize_to_isize,
+};
 
 const PERFECT_THRESHOLD: f64 = 1.0;
 
@@ -32,14 +42,23 @@ fn grade_key(d: f64) -> f64 {
     })
 }
 
-fn get_intersection(target: &NoteSeries, input: &NoteSeries) -> (Vec<(f64, f64)>, usize) {
+/// Pairs the target and sung notes whose pitch is graded, and counts the target notes not sung.
+///
+/// Freestyle notes count towards coverage but their pitch is not graded, while golden notes are
+/// paired twice so that they count double.
+fn get_intersection<'a>(
+    target: &'a NoteSeries,
+    input: &'a NoteSeries,
+) -> (Vec<(&'a Note, &'a Note)>, usize) {
     let mut misses = 0usize;
     let mut intersection = Vec::new();
     for (t, i) in target.iter().zip(input) {
         match (t, i) {
-            (Some(x), Some(y)) => {
-                intersection.push((x.note_number, y.note_number));
-            }
+            (Some(x), Some(y)) => match x.kind {
+                NoteKind::Normal => intersection.push((x, y)),
+                NoteKind::Golden => intersection.extend([(x, y), (x, y)]),
+                NoteKind::Freestyle => {}
+            },
             (Some(_), None) => misses += 1,
             _ => {}
         }
@@ -54,17 +73,379 @@ fn grade_coverage(misses: usize, len: usize) -> f64 {
 fn grade_timing(time_shift_secs: f64) -> f64 {
     match time_shift_secs.abs() {
         0.0..0.05 => 1.,
-        0.05..0.25 => (time_shift_secs.abs() - 0.05) / 0.2,
+        0.05..0.25 => 1. - (time_shift_secs.abs() - 0.05) / 0.2,
         _ => 0.0,
     }
 }
 
+/// Evaluates how closely the tempo of the singing kept to the tempo of the target, from the
+/// ratio between them.
+fn grade_tempo(tempo_ratio: f64) -> f64 {
+    match tempo_ratio.ln().abs() {
+        0.0..0.02 => 1.,
+        d @ 0.02..0.1 => 1. - (d - 0.02) / 0.08,
+        _ => 0.,
+    }
+}
+
+/// Averages the timing grade of every frame where the target has a note.
+fn grade_local_timing(target: &NoteSeries, timing_deviations: &[f64]) -> f64 {
+    let grades = target
+        .iter()
+        .zip(timing_deviations)
+        .filter(|(t, _)| t.is_some())
+        .map(|(_, &d)| grade_timing(d))
+        .collect::<Vec<_>>();
+    if grades.is_empty() {
+        return 0.;
+    }
+    grades.iter().sum::<f64>() / usize_to_f64(grades.len())
+}
+
+/// Median of `values` where each value counts in proportion to its weight, or the plain median
+/// when no value has any weight.
+///
+/// `values` must be sorted by value.
+fn weighted_median(values: &[(f64, f64)]) -> f64 {
+    let half = values.iter().map(|(_, w)| w).sum::<f64>() / 2.;
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    if half <= 0. {
+        return median(&values.iter().map(|&(v, _)| v).collect::<Box<_>>());
+    }
+    let mut cumulative = 0.;
+    for &(value, weight) in values {
+        cumulative += weight;
+        if cumulative >= half {
+            return value;
+        }
+    }
+    f64::NAN
+}
+
+/// Finds the time ranges in seconds where the sung notes were detected with a clarity below
+/// `threshold`.
+///
+/// The singing is never shifted or warped, only padded with silence at its end, so the ranges are
+/// in seconds from the start of the recording.
+fn low_confidence_regions(input: &NoteTimeSeries, threshold: f64) -> Box<[Range<f64>]> {
+    let dt = *input.interval();
+    let mut regions = Vec::<Range<f64>>::new();
+    for (i, note) in input.samples().iter().enumerate() {
+        if note.as_ref().is_some_and(|n| n.clarity() < threshold) {
+            let (start, end) = (dt * usize_to_f64(i), dt * usize_to_f64(i + 1));
+            match regions.last_mut() {
+                Some(last) if (last.end - start).abs() < dt / 2. => last.end = end,
+                _ => regions.push(start..end),
+            }
+        }
+    }
+    regions.into()
+}
+
+/// Grades of the part of the target sung on a syllable or a line of the lyrics.
+#[derive(Debug, Clone)]
+pub struct LyricAccuracy {
+    /// Text of the syllable, or of every syllable of the line.
+    pub text: String,
+    /// Zero-based index of the line of the lyrics.
+    pub line: usize,
+    /// Fraction of the frames of the target that were sung.
+    pub coverage: f64,
+    /// Median pitch grade of the sung frames, if any were sung on a note whose pitch is graded.
+    pub pitch: Option<f64>,
+    /// Median deviation of the sung pitch from the target in cents, negative when flat.
+    pub cents: Option<f64>,
+}
+
+/// The frames of the target sung on a syllable, a line of the lyrics or a note.
+#[derive(Default)]
+struct Tally {
+    frames: usize,
+    sung: usize,
+    note_numbers: Vec<f64>,
+    grades: Vec<f64>,
+    deviations: Vec<f64>,
+}
+
+impl Tally {
+    fn add(&mut self, target: &Note, input: Option<&Note>) {
+        self.frames += 1;
+        let Some(input) = input else {
+            return;
+        };
+        self.sung += 1;
+        self.note_numbers.push(input.note_number);
+        if target.kind != NoteKind::Freestyle {
+            let deviation = target.deviation(input.note_number);
+            self.grades.push(grade_key(deviation.abs()));
+            self.deviations.push(deviation * 100.);
+        }
+    }
+
+    fn grade(self, text: String, line: usize) -> LyricAccuracy {
+        LyricAccuracy {
+            text,
+            line,
+            coverage: usize_to_f64(self.sung) / usize_to_f64(self.frames),
+            pitch: sorted_median(self.grades),
+            cents: sorted_median(self.deviations),
+        }
+    }
+}
+
+/// Semitones within which a sung frame is on the pitch of a note, when finding where the note was
+/// started.
+const ONSET_TOLERANCE: f64 = 1.;
+/// Seconds before a note starts in the target that it may be found to have been started early.
+const ONSET_WINDOW_SECS: f64 = 0.25;
+
+/// Grades of a single note of the target.
+#[derive(Debug, Clone)]
+pub struct NoteAccuracy {
+    /// Zero-based index of the note among the notes of the target, if the target numbers them.
+    pub index: Option<usize>,
+    /// Where the note starts in the target, if the target is a MIDI file or a score.
+    pub position: Option<Position>,
+    /// Time range in seconds of the recording that the note was aligned with.
+    pub range: Range<f64>,
+    /// Note number of the target as written, before it was shifted into the key it was sung in.
+    pub note_number: f64,
+    /// Median note number sung on the note, if any of it was sung.
+    pub sung: Option<f64>,
+    /// Fraction of the frames of the note that were sung.
+    pub coverage: f64,
+    /// Median pitch grade of the sung frames, if any were sung and the pitch of the note is graded.
+    pub pitch: Option<f64>,
+    /// Median deviation of the sung pitch from the note in the key it was sung in, in cents,
+    /// negative when flat.
+    pub cents: Option<f64>,
+    /// Standard deviation in cents of the deviation of every sung frame, so a wide spread means
+    /// the pitch wavered over the note.
+    pub spread: Option<f64>,
+    /// Seconds that the note was started on its pitch later than the target, or earlier when
+    /// negative, if it was ever sung on its pitch.
+    pub onset: Option<f64>,
+}
+
+impl Tally {
+    fn grade_note(
+        self,
+        index: Option<usize>,
+        range: Range<f64>,
+        note_number: f64,
+        onset: Option<f64>,
+    ) -> NoteAccuracy {
+        let spread = (!self.deviations.is_empty()).then(|| {
+            let len = usize_to_f64(self.deviations.len());
+            let mean = self.deviations.iter().sum::<f64>() / len;
+            (self
+                .deviations
+                .iter()
+                .map(|d| (d - mean).powi(2))
+                .sum::<f64>()
+                / len)
+                .sqrt()
+        });
+        NoteAccuracy {
+            index,
+            position: None,
+            range,
+            note_number,
+            sung: sorted_median(self.note_numbers),
+            coverage: usize_to_f64(self.sung) / usize_to_f64(self.frames),
+            pitch: sorted_median(self.grades),
+            cents: sorted_median(self.deviations),
+            spread,
+            onset,
+        }
+    }
+}
+
+/// Finds how many frames after the start of the note at `frames` it was first sung on its pitch,
+/// counting the frames just before it that were already sung on its pitch as an early start.
+fn find_onset(
+    note: &Note,
+    input: &NoteSeries,
+    frames: &Range<usize>,
+    window: usize,
+) -> Option<isize> {
+    let on_pitch = |i: usize| {
+        input
+            .get(i)
+            .and_then(Option::as_ref)
+            .is_some_and(|n| note.distance(n.note_number) < ONSET_TOLERANCE)
+    };
+    let mut onset = frames.clone().find(|&i| on_pitch(i))?;
+    while onset > frames.start.saturating_sub(window) && on_pitch(onset - 1) {
+        onset -= 1;
+    }
+    Some(usize_to_isize(onset) - usize_to_isize(frames.start))
+}
+
+/// Grades every note of the target, where a note is a run of frames with the same note index, or
+/// of equal frames where there is none, given the interval `dt` between the frames in seconds.
+///
+/// `written` maps the start of a note in seconds and its note number at its start, before any
+/// bend, to the note number of the target as written.
+fn grade_notes(
+    target: &NoteSeries,
+    input: &NoteSeries,
+    dt: f64,
+    written: impl Fn(f64, f64) -> f64,
+) -> Box<[NoteAccuracy]> {
+    let window = f64_to_usize(ONSET_WINDOW_SECS / dt);
+    let mut notes = Vec::new();
+    let mut start = 0;
+    while start < target.len() {
+        let Some(note) = &target[start] else {
+            start += 1;
+            continue;
+        };
+        let same_note = |t: &Option<Note>| {
+            t.as_ref().is_some_and(|t| match note.index {
+                Some(_) => t.index == note.index,
+                None => t == note,
+            })
+        };
+        let end = start + target[start..].iter().take_while(|t| same_note(t)).count();
+        let mut tally = Tally::default();
+        for (i, frame) in target[start..end].iter().flatten().enumerate() {
+            tally.add(frame, input.get(start + i).and_then(Option::as_ref));
+        }
+        let frames = start..end;
+        let onset = find_onset(note, input, &frames, window).map(|o| dt * isize_to_f64(o));
+        let range = dt * usize_to_f64(start)..dt * usize_to_f64(end);
+        notes.push(tally.grade_note(
+            note.index,
+            range.clone(),
+            written(range.start, note.note_number),
+            onset,
+        ));
+        start = end;
+    }
+    notes.into()
+}
+
+fn sorted_median(mut values: Vec<f64>) -> Option<f64> {
+    (!values.is_empty()).then(|| {
+        values.sort_unstable_by(f64::total_cmp);
+        median(&values)
+    })
+}
+
+/// Grades the frames of the target sung on each syllable, in the order they are first sung, and
+/// on each line of the lyrics.
+fn grade_lyrics(
+    target: &NoteSeries,
+    input: &NoteSeries,
+) -> (Box<[LyricAccuracy]>, Box<[LyricAccuracy]>) {
+    let mut syllables = Vec::<(Arc<Lyric>, Tally)>::new();
+    let mut indices = HashMap::<*const Lyric, usize>::new();
+    let mut lines = BTreeMap::<usize, Tally>::new();
+    for (t, i) in target.iter().zip(input) {
+        let Some((t, lyric)) = t.as_ref().and_then(|t| Some((t, t.lyric.as_ref()?))) else {
+            continue;
+        };
+        let index = *indices.entry(Arc::as_ptr(lyric)).or_insert_with(|| {
+            syllables.push((lyric.clone(), Tally::default()));
+            syllables.len() - 1
+        });
+        syllables[index].1.add(t, i.as_ref());
+        lines.entry(lyric.line).or_default().add(t, i.as_ref());
+    }
+
+    let mut texts = BTreeMap::<usize, String>::new();
+    for (lyric, _) in &syllables {
+        texts
+            .entry(lyric.line)
+            .or_default()
+            .push_str(&lyric.syllable);
+    }
+    let lines = lines
+        .into_iter()
+        .map(|(line, tally)| {
+            let text = texts.get(&line).map_or("", |t| t.trim()).to_owned();
+            tally.grade(text, line)
+        })
+        .collect();
+    let syllables = syllables
+        .into_iter()
+        .map(|(lyric, tally)| tally.grade(lyric.syllable.trim().to_owned(), lyric.line))
+        .collect();
+    (syllables, lines)
+}
+
+/// Where a phrase of the target was sung, relative to the target as written.
+#[derive(Debug, Clone)]
+pub struct PhraseOffset {
+    /// Time range in seconds of the recording that the phrase was aligned with.
+    pub range: Range<f64>,
+    /// Seconds that the phrase was sung later than the target, or earlier when negative.
+    pub late_secs: f64,
+    /// Semitones that the phrase was sung above the target, or below when negative.
+    pub key_shift: f64,
+}
+
+/// Averages the key grade of every phrase by its length, or `None` if there are no phrases.
+fn grade_phrase_keys(phrases: &[PhraseOffset]) -> Option<f64> {
+    let (graded, length) = phrases
+        .iter()
+        .map(|p| (p.range.end - p.range.start, p.key_shift))
+        .fold((0., 0.), |(graded, length), (l, key_shift)| {
+            (
+                l.mul_add(grade_key(distance_to_nearest_octave(key_shift)), graded),
+                length + l,
+            )
+        });
+    (length > 0.).then(|| graded / length)
+}
+
+#[derive(Debug, Clone)]
+pub struct GradeOptions {
+    /// Weighs the pitch error of every frame by the confidence it was detected with.
+    pub confidence_weighted: bool,
+    /// Clarity below which detected notes are reported as low confidence.
+    pub low_confidence_threshold: f64,
+}
+
+impl Default for GradeOptions {
+    fn default() -> Self {
+        Self {
+            confidence_weighted: false,
+            low_confidence_threshold: 0.95,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Accuracy {
     pub coverage: f64,
     pub timing: f64,
     pub pitch: f64,
     pub key: f64,
+    /// Fraction of the sung frames that agree with the key the singing was graded in, so a low
+    /// confidence means that no single key fits the singing well.
+    pub key_confidence: f64,
+    /// Ratio of the tempo the singing was graded at to the tempo of the target.
+    pub tempo_ratio: f64,
+    /// How closely the singing kept to the tempo of the target, which is graded apart from the
+    /// total accuracy.
+    pub tempo: f64,
+    /// Seconds that every frame of the recording was sung later than the target at the tempo it
+    /// was sung in, or earlier when negative.
+    pub timing_deviations: Box<[f64]>,
+    /// Time ranges in seconds from the start of the recording where the sung pitch is uncertain.
+    pub low_confidence: Box<[Range<f64>]>,
+    /// Grades of every syllable of the lyrics of the target, which has none if it has no lyrics.
+    pub syllables: Box<[LyricAccuracy]>,
+    /// Grades of every line of the lyrics of the target.
+    pub lines: Box<[LyricAccuracy]>,
+    /// Offsets of every phrase of the target, which has none unless the phrases were aligned.
+    pub phrases: Box<[PhraseOffset]>,
+    /// Grades of every note of the target, in order.
+    pub notes: Box<[NoteAccuracy]>,
 }
 
 impl Accuracy {
@@ -83,21 +464,260 @@ impl Accuracy {
 impl Accuracy {
     pub fn new(
         target: &NoteSeries,
-        input: &NoteSeries,
-        time_shift_secs: f64,
-        note_shift: f64,
+        input: &NoteTimeSeries,
+        timing_deviations: &[f64],
+        key_shift: KeyShift,
+        phrases: Box<[PhraseOffset]>,
+        tempo_ratio: f64,
+        options: &GradeOptions,
     ) -> Self {
-        let (intersection, misses) = get_intersection(target, input);
-        let mut individual_note_shifts = intersection
-            .into_iter()
-            .map(|(x, y)| grade_key((x - y).abs()))
-            .collect::<Box<_>>();
-        individual_note_shifts.sort_unstable_by(f64::total_cmp);
+        let (intersection, misses) = get_intersection(target, input.samples());
+        let (syllables, lines) = grade_lyrics(target, input.samples());
+        let notes = grade_notes(
+            target,
+            input.samples(),
+            *input.interval(),
+            |start, note_number| {
+                let shift = phrases
+                    .iter()
+                    .find(|p| p.range.contains(&start))
+                    .map_or(key_shift.semitones, |p| p.key_shift);
+                note_number - shift
+            },
+        );
+        let pitch = if options.confidence_weighted {
+            let mut individual_note_shifts = intersection
+                .into_iter()
+                .map(|(x, y)| (grade_key(x.distance(y.note_number)), y.clarity()))
+                .collect::<Box<_>>();
+            individual_note_shifts.sort_unstable_by(|(a, _), (b, _)| a.total_cmp(b));
+            weighted_median(&individual_note_shifts)
+        } else {
+            let mut individual_note_shifts = intersection
+                .into_iter()
+                .map(|(x, y)| grade_key(x.distance(y.note_number)))
+                .collect::<Box<_>>();
+            individual_note_shifts.sort_unstable_by(f64::total_cmp);
+            median(&individual_note_shifts)
+        };
         Self {
             coverage: grade_coverage(misses, target.len()),
-            timing: grade_timing(time_shift_secs),
-            pitch: median(&individual_note_shifts),
-            key: grade_key(distance_to_nearest_octave(note_shift)),
+            timing: grade_local_timing(target, timing_deviations),
+            pitch,
+            key: grade_phrase_keys(&phrases)
+                .unwrap_or_else(|| grade_key(distance_to_nearest_octave(key_shift.semitones))),
+            key_confidence: key_shift.confidence,
+            tempo_ratio,
+            tempo: grade_tempo(tempo_ratio),
+            timing_deviations: timing_deviations.into(),
+            low_confidence: low_confidence_regions(input, options.low_confidence_threshold),
+            syllables,
+            lines,
+            phrases,
+            notes,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use rstest::rstest;
+
+    use super::{
+        Accuracy, GradeOptions, LyricAccuracy, grade_lyrics, grade_notes, low_confidence_regions,
+        weighted_median,
+    };
+    use crate::{
+        align::KeyShift,
+        core::{Detection, DynNoteTimeSeries, Lyric, Note, NoteTimeSeries},
+    };
+
+    fn detected(note_number: f64, clarity: f64) -> Note {
+        let detection = Detection {
+            frequency: 440.,
+            clarity,
+            rms: 0.1,
+        };
+        Note::detected(note_number, detection)
+    }
+
+    #[test]
+    fn lyrics() {
+        let lyric = |syllable: &str, line| {
+            Arc::new(Lyric {
+                syllable: syllable.to_owned(),
+                line,
+            })
+        };
+        let (love, me, do_) = (lyric("Love", 0), lyric(" me", 0), lyric("do", 1));
+        let note = |lyric: &Arc<Lyric>| {
+            let mut note = Note::new(60.);
+            note.lyric = Some(lyric.clone());
+            Some(note)
+        };
+        let target = [
+            note(&love),
+            note(&love),
+            note(&me),
+            None,
+            note(&do_),
+            note(&do_),
+        ];
+        let input = [59.6, 59.6, 60., 60., 61., 0.].map(|n| (n > 0.).then(|| Note::new(n)));
+
+        let (syllables, lines) = grade_lyrics(&target, &input);
+        let grades = |accuracies: &[LyricAccuracy]| {
+            accuracies
+                .iter()
+                .map(|a| (a.text.clone(), a.line, a.coverage, a.cents.map(f64::round)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            grades(&syllables),
+            [
+                ("Love".to_owned(), 0, 1., Some(-40.)),
+                ("me".to_owned(), 0, 1., Some(0.)),
+                ("do".to_owned(), 1, 0.5, Some(100.)),
+            ]
+        );
+        assert_eq!(
+            grades(&lines),
+            [
+                ("Love me".to_owned(), 0, 1., Some(-40.)),
+                ("do".to_owned(), 1, 0.5, Some(100.)),
+            ]
+        );
+    }
+
+    #[test]
+    fn notes() {
+        let note = |n: f64| (n > 0.).then(|| Note::new(n));
+        let target = [60., 60., 60., 0., 62., 62., 62.].map(note);
+        let input = [0., 60.2, 60.2, 62., 62., 62.5, 0.].map(note);
+
+        let notes = grade_notes(&target, &input, 0.1, |_, n| n - 2.);
+        let round = |x: f64| (x * 100.).round() / 100.;
+        let grades = notes
+            .iter()
+            .map(|n| {
+                (
+                    (round(n.range.start), round(n.range.end)),
+                    n.note_number,
+                    n.sung.map(round),
+                    round(n.coverage),
+                    n.cents.map(f64::round),
+                    n.spread.map(f64::round),
+                    n.onset.map(round),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            grades,
+            [
+                (
+                    (0., 0.3),
+                    58.,
+                    Some(60.2),
+                    0.67,
+                    Some(20.),
+                    Some(0.),
+                    Some(0.1)
+                ),
+                (
+                    (0.4, 0.7),
+                    60.,
+                    Some(62.25),
+                    0.67,
+                    Some(25.),
+                    Some(25.),
+                    Some(-0.1)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_notes() {
+        let note = |index: usize| {
+            let mut note = Note::new(60.);
+            note.index = Some(index);
+            Some(note)
+        };
+        let target = [note(0), note(0), note(1), note(1), note(1)];
+        let input = target.clone();
+
+        let notes = grade_notes(&target, &input, 0.1, |_, n| n);
+        let ranges = notes
+            .iter()
+            .map(|n| ((n.range.start * 10.).round(), (n.range.end * 10.).round()))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [(0., 2.), (2., 5.)]);
+    }
+
+    #[rstest]
+    #[case(&[(1., 1.), (2., 1.), (3., 1.)], 2.)]
+    #[case(&[(1., 1.), (2., 1.), (3., 4.)], 3.)]
+    #[case(&[(1., 4.), (2., 1.), (3., 1.)], 1.)]
+    #[case(&[(1., 0.), (2., 0.), (3., 0.), (4., 0.)], 2.5)]
+    fn weighted_medians(#[case] values: &[(f64, f64)], #[case] expected: f64) {
+        assert!((weighted_median(values) - expected).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn weighted_median_empty() {
+        assert!(weighted_median(&[]).is_nan());
+    }
+
+    #[rstest]
+    // a frame at the threshold is confident
+    #[case(&[0.5, 0.8, 0.5], &[(0., 0.1), (0.2, 0.3)])]
+    #[case(&[0.5, 0.79, 0.5], &[(0., 0.3)])]
+    // silence ends a region
+    #[case(&[0.5, 0., 0.5], &[(0., 0.1), (0.2, 0.3)])]
+    #[case(&[0.9, 0.5, 0.5], &[(0.1, 0.3)])]
+    fn low_confidence(#[case] clarities: &[f64], #[case] expected: &[(f64, f64)]) {
+        let notes = clarities
+            .iter()
+            .map(|&c| (c > 0.).then(|| detected(60., c)))
+            .collect();
+        let input: NoteTimeSeries = DynNoteTimeSeries::new(notes, 0.1.into()).into();
+        let regions = low_confidence_regions(&input, 0.8)
+            .iter()
+            .map(|r| ((r.start * 10.).round() / 10., (r.end * 10.).round() / 10.))
+            .collect::<Vec<_>>();
+        assert_eq!(regions, expected);
+    }
+
+    #[test]
+    fn confidence_weighted() {
+        let target = [60., 60., 60.].map(|n| Some(Note::new(n)));
+        // only the confident frame is in tune
+        let notes = [(60., 1.), (61., 0.1), (61., 0.1)]
+            .map(|(n, c)| Some(detected(n, c)))
+            .into();
+        let input: NoteTimeSeries = DynNoteTimeSeries::new(notes, 0.1.into()).into();
+        let key_shift = KeyShift {
+            semitones: 0.,
+            confidence: 1.,
+        };
+        let pitch = |confidence_weighted| {
+            let options = GradeOptions {
+                confidence_weighted,
+                ..GradeOptions::default()
+            };
+            Accuracy::new(
+                &target,
+                &input,
+                &[0.; 3],
+                key_shift,
+                [].into(),
+                1.,
+                &options,
+            )
+            .pitch
+        };
+        assert!(pitch(true) > pitch(false));
+    }
+}
@@ -7,13 +7,23 @@ pub struct UnpaddedTargetMelody {
 }
 
 impl crate::core::RawUnpaddedTargetMelody {
-    pub fn zero_order_hold(self, input: &UnpaddedInputMelody) -> UnpaddedTargetMelody {
+    /// Samples the target at every frame of the input, `shift_secs` later into the target so
+    /// that a shift by a fraction of a frame is kept.
+    ///
+    /// The target is stretched to the tempo of the singing, which is `tempo_ratio` times the
+    /// tempo of the target.
+    pub fn zero_order_hold(
+        &self,
+        input: &UnpaddedInputMelody,
+        shift_secs: f64,
+        tempo_ratio: f64,
+    ) -> UnpaddedTargetMelody {
         let mut target = Vec::new();
         let mut last_event = None;
-        let mut t = OrderedFloat(0.);
+        let mut t = OrderedFloat(shift_secs);
         let dt = input.notes.interval();
         for event in self.note_events() {
-            while t < event.time {
+            while t * tempo_ratio < event.time {
                 target.push(last_event.clone().and_then(|o: Timed<_>| o.value));
                 t += dt;
             }
@@ -4,9 +4,18 @@ mod error;
 mod grade;
 mod interpolate;
 mod pad;
+mod postprocess;
 mod run;
 #[cfg(feature = "visualise")]
 mod visualise;
 
-pub use grade::Accuracy;
-pub use run::run;
+pub use align::{PhraseOptions, StepPattern, TempoOptions, WarpOptions};
+pub use core::{
+    BarBeat, Cepstrum, ChannelMode, HarmonicProductSpectrum, InputOptions, Lyric, McLeod, Midi,
+    NoteKind, PYin, Pitch, PitchEstimator, Polyphony, Position, SegmentOptions, TargetOptions,
+    TempoMap, TimeDivision, TrackSelection, TranscribeOptions, Tuning, WindowFunction, Yin,
+    open_midi,
+};
+pub use grade::{Accuracy, GradeOptions, LyricAccuracy, NoteAccuracy, PhraseOffset};
+pub use postprocess::{PitchFilter, PostProcessOptions};
+pub use run::{Options, PitchAlgorithm, run, run_with_options, transcribe};
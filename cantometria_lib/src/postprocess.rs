@@ -0,0 +1,192 @@
+use ordered_float::OrderedFloat;
+
+use crate::core::{NoteSeries, UnpaddedInputMelody, f64_to_usize, median};
+
+/// Filter replacing the pitch of every voiced frame with a statistic of its voiced neighbourhood.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PitchFilter {
+    /// Median over a window of the given number of frames.
+    Median(usize),
+    /// Median of the most common semitone over a window of the given number of frames.
+    Mode(usize),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PostProcessOptions {
+    /// Voiced runs shorter than this many seconds are made unvoiced.
+    pub min_voiced_secs: f64,
+    /// Number of frames on either side that octave errors are corrected against.
+    pub octave_correction: Option<usize>,
+    pub filter: Option<PitchFilter>,
+}
+
+/// Voiced note numbers within `radius` frames of `i`, excluding `i` itself if `exclusive`.
+fn neighbourhood(notes: &NoteSeries, i: usize, radius: usize, exclusive: bool) -> Vec<f64> {
+    let start = i.saturating_sub(radius);
+    let end = (i + radius + 1).min(notes.len());
+    notes[start..end]
+        .iter()
+        .enumerate()
+        .filter(|&(j, _)| !exclusive || start + j != i)
+        .filter_map(|(_, n)| n.as_ref().map(|n| n.note_number))
+        .collect()
+}
+
+fn sorted_median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable_by(f64::total_cmp);
+    Some(median(&values))
+}
+
+fn remove_short_runs(notes: &mut NoteSeries, min_len: usize) {
+    let mut start = 0;
+    while start < notes.len() {
+        let len = notes[start..].iter().take_while(|n| n.is_some()).count();
+        if len == 0 {
+            start += 1;
+            continue;
+        }
+        if len < min_len {
+            notes[start..start + len].fill(None);
+        }
+        start += len;
+    }
+}
+
+/// Moves every voiced frame lying a whole number of octaves (within a semitone) away from the
+/// median of its neighbours back into their octave.
+fn correct_octaves(notes: &mut NoteSeries, radius: usize) {
+    let references = (0..notes.len())
+        .map(|i| {
+            notes[i]
+                .as_ref()
+                .and_then(|_| sorted_median(neighbourhood(notes, i, radius, true)))
+        })
+        .collect::<Box<_>>();
+    for (note, reference) in notes.iter_mut().zip(references) {
+        if let (Some(note), Some(reference)) = (note, reference) {
+            let octaves = ((note.note_number - reference) / 12.).round();
+            let remainder = 12.0f64.mul_add(-octaves, note.note_number - reference);
+            if octaves != 0. && remainder.abs() < 1. {
+                note.note_number -= 12. * octaves;
+            }
+        }
+    }
+}
+
+fn filter(notes: &mut NoteSeries, filter: PitchFilter) {
+    let (PitchFilter::Median(len) | PitchFilter::Mode(len)) = filter;
+    let radius = len / 2;
+    let filtered = (0..notes.len())
+        .map(|i| {
+            notes[i].as_ref()?;
+            let neighbours = neighbourhood(notes, i, radius, false);
+            match filter {
+                PitchFilter::Median(_) => sorted_median(neighbours),
+                PitchFilter::Mode(_) => {
+                    let semitone = |n: f64| OrderedFloat(n.round());
+                    let mode = neighbours.iter().map(|&n| semitone(n)).max_by_key(|&s| {
+                        neighbours.iter().filter(|&&n| semitone(n) == s).count()
+                    })?;
+                    sorted_median(
+                        neighbours
+                            .into_iter()
+                            .filter(|&n| semitone(n) == mode)
+                            .collect(),
+                    )
+                }
+            }
+        })
+        .collect::<Box<_>>();
+    for (note, value) in notes.iter_mut().zip(filtered) {
+        if let (Some(note), Some(value)) = (note, value) {
+            note.note_number = value;
+        }
+    }
+}
+
+impl UnpaddedInputMelody {
+    /// Cleans up the sung pitch track, removing short voiced blips first, then correcting
+    /// octave errors, then filtering.
+    ///
+    /// Only the note numbers are corrected and filtered. The detection of every frame keeps the
+    /// frequency the pitch detector estimated, so it may differ from the note number.
+    pub fn post_process(&mut self, options: &PostProcessOptions) {
+        let dt = *self.notes.interval();
+        let notes = self.notes.samples_mut();
+        remove_short_runs(notes, f64_to_usize((options.min_voiced_secs / dt).ceil()));
+        if let Some(radius) = options.octave_correction {
+            correct_octaves(notes, radius);
+        }
+        if let Some(pitch_filter) = options.filter {
+            filter(notes, pitch_filter);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{PitchFilter, correct_octaves, filter, remove_short_runs};
+    use crate::core::Note;
+
+    fn series(notes: &[Option<f64>]) -> Vec<Option<Note>> {
+        notes.iter().map(|n| n.map(Note::new)).collect()
+    }
+
+    fn note_numbers(notes: &[Option<Note>]) -> Vec<Option<f64>> {
+        notes
+            .iter()
+            .map(|n| n.as_ref().map(|n| n.note_number))
+            .collect()
+    }
+
+    #[test]
+    fn short_runs() {
+        let mut notes = series(&[Some(60.), None, Some(60.), Some(60.), None, Some(62.)]);
+        remove_short_runs(&mut notes, 2);
+        assert_eq!(
+            note_numbers(&notes),
+            [None, None, Some(60.), Some(60.), None, None]
+        );
+    }
+
+    #[test]
+    fn octaves() {
+        let raw = [60., 60.2, 72.1, 59.9, 60.1, 60., 48.2, 60., 59.8];
+        let mut notes = series(&raw.map(Some));
+        correct_octaves(&mut notes, 2);
+        let expected = [60., 60.2, 60.1, 59.9, 60.1, 60., 60.2, 60., 59.8];
+        assert!(
+            note_numbers(&notes)
+                .into_iter()
+                .zip(expected)
+                .all(|(n, e)| n.is_some_and(|n| (n - e).abs() < 1e-9))
+        );
+    }
+
+    #[rstest]
+    #[case(PitchFilter::Median(3), [61.05, 61.2, 61.1, 61.1, 61.05])]
+    #[case(PitchFilter::Mode(3), [61.05, 61.05, 61., 61.05, 61.05])]
+    fn filters(#[case] pitch_filter: PitchFilter, #[case] expected: [f64; 5]) {
+        let mut notes = series(&[
+            Some(61.2),
+            Some(60.9),
+            Some(67.),
+            Some(61.1),
+            Some(61.),
+            None,
+        ]);
+        filter(&mut notes, pitch_filter);
+        assert!(notes[5].is_none());
+        assert!(
+            note_numbers(&notes[..5])
+                .into_iter()
+                .zip(expected)
+                .all(|(n, e)| n.is_some_and(|n| (n - e).abs() < 1e-9))
+        );
+    }
+}
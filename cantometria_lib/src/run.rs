@@ -3,46 +3,266 @@ use std::path::Path;
 use crate::align;
 use crate::core;
 use crate::error::RunError;
-use crate::grade::Accuracy;
+use crate::grade::{Accuracy, GradeOptions, PhraseOffset};
+use crate::postprocess::PostProcessOptions;
 
+/// The pitch estimator used to analyse the singing recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PitchAlgorithm {
+    #[default]
+    Yin,
+    PYin,
+    McLeod,
+    HarmonicProductSpectrum,
+    Cepstrum,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub target: core::TargetOptions,
+    pub input: core::InputOptions,
+    /// Tuning that both the target notes and the sung frequencies are measured in.
+    pub tuning: core::Tuning,
+    pub pitch_algorithm: PitchAlgorithm,
+    pub post_process: PostProcessOptions,
+    pub grade: GradeOptions,
+    /// Warps the target onto the singing after shifting it, to follow a singer who rushes or
+    /// drags, instead of keeping to a single time shift.
+    pub warp: Option<align::WarpOptions>,
+    /// Aligns every phrase of the target on its own after shifting it, to follow a singer who
+    /// drifts through a long song.
+    pub phrases: Option<align::PhraseOptions>,
+    /// Searches for the tempo the target was sung at and stretches the target to it, instead of
+    /// keeping to the tempo of the target.
+    pub tempo: Option<align::TempoOptions>,
+}
+
+/// Opens the recording in `wav_file`, estimates the sung pitch and cleans it up.
+fn analyse<P: AsRef<Path>>(
+    wav_file: P,
+    options: &Options,
+) -> Result<core::UnpaddedInputMelody, RunError> {
+    let wav = crate::core::open_wav(wav_file)?;
+    let mut input_unpadded = {
+        let (input, tuning) = (&options.input, &options.tuning);
+        match options.pitch_algorithm {
+            PitchAlgorithm::Yin => {
+                core::UnpaddedInputMelody::new(wav, input, tuning, core::Yin::default())
+            }
+            PitchAlgorithm::PYin => {
+                core::UnpaddedInputMelody::new(wav, input, tuning, core::PYin::default())
+            }
+            PitchAlgorithm::McLeod => {
+                core::UnpaddedInputMelody::new(wav, input, tuning, core::McLeod::default())
+            }
+            PitchAlgorithm::HarmonicProductSpectrum => core::UnpaddedInputMelody::new(
+                wav,
+                input,
+                tuning,
+                core::HarmonicProductSpectrum::default(),
+            ),
+            PitchAlgorithm::Cepstrum => {
+                core::UnpaddedInputMelody::new(wav, input, tuning, core::Cepstrum::default())
+            }
+        }?
+    };
+    input_unpadded.post_process(&options.post_process);
+    Ok(input_unpadded)
+}
+
+/// Grades the singing in `wav_file` against the melody in `target_file` with the default options.
+///
+/// # Errors
+/// See [`run_with_options`].
+pub fn run<P: AsRef<Path>>(target_file: P, wav_file: P) -> Result<Accuracy, RunError> {
+    run_with_options(target_file, wav_file, &Options::default())
+}
+
+/// Grades the singing in `wav_file` against the melody in `target_file`.
+///
+/// The target is a MIDI file, an `UltraStar` song, a `MusicXML` score or a reference recording
+/// (`.wav`) analysed the same way as the singing.
+///
 /// # Errors
-/// - opening the midi file failed
+/// - the range of tempo ratios is not valid
+/// - opening the target file failed
 /// - opening the wav file failed
-/// - creating a new raw unpadded target melody failed
 /// - creating a new unpadded target melody failed
 /// - input melody is empty
+/// - the singing is not on any note of the target
 /// - (visualise) plotting failed
-pub fn run<P: AsRef<Path>>(midi_file: P, wav_file: P) -> Result<Accuracy, RunError> {
+pub fn run_with_options<P: AsRef<Path>>(
+    target_file: P,
+    wav_file: P,
+    options: &Options,
+) -> Result<Accuracy, RunError> {
+    if let Some(tempo) = options.tempo.as_ref().filter(|tempo| !tempo.is_valid()) {
+        return Err(RunError::InvalidTempoRange {
+            min_ratio: tempo.min_ratio,
+            max_ratio: tempo.max_ratio,
+        });
+    }
     #[cfg(feature = "visualise")]
-    let fp = plot_target_file(&midi_file, &wav_file);
-    let midi = core::open_midi(midi_file)?;
-    let target_unpadded_raw = core::RawUnpaddedTargetMelody::new(&midi)?;
-    let wav = crate::core::open_wav(wav_file)?;
-    let input_unpadded = core::UnpaddedInputMelody::new(wav)?;
+    let fp = plot_target_file(&target_file, &wav_file);
+    let target_file = target_file.as_ref();
+    let is_recording = target_file
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("wav"));
+    let target_unpadded_raw = if is_recording {
+        core::RawUnpaddedTargetMelody::from_recording(
+            &analyse(target_file, options)?,
+            options.target.segmentation.as_ref(),
+        )
+    } else {
+        core::open_target(target_file, &options.target, &options.tuning)?
+    };
+    let input_unpadded = analyse(wav_file, options)?;
+
+    let dt = *input_unpadded.notes.interval();
+    let stretch = |tempo_ratio| {
+        target_unpadded_raw
+            .zero_order_hold(&input_unpadded, 0., tempo_ratio)
+            .notes
+            .samples()
+            .clone()
+    };
+    let input_samples = input_unpadded.notes.samples();
+    let (tempo_ratio, time_shift) = options
+        .tempo
+        .as_ref()
+        .map_or_else(
+            || align::compute_time_shift(&stretch(1.), input_samples).map(|shift| (1., shift)),
+            |tempo| align::compute_tempo_ratio(stretch, input_samples, tempo),
+        )
+        .ok_or(RunError::InputMelodyEmpty)?;
+    let time_shift_secs = dt * time_shift;
 
-    let target_unpadded = target_unpadded_raw.zero_order_hold(&input_unpadded);
-    let (target_unaligned, input_series) =
+    let target_unpadded =
+        target_unpadded_raw.zero_order_hold(&input_unpadded, time_shift_secs, tempo_ratio);
+    let (target_aligned, input_series) =
         crate::pad::zero_pad_shorter_series(target_unpadded, input_unpadded);
 
-    let mut target = target_unaligned.notes().samples().clone();
+    let mut target = target_aligned.notes().samples().clone();
     let input = input_series.notes().samples();
-    let time_shift = align::compute_time_shift(&target, input).ok_or(RunError::InputMelodyEmpty)?;
-    align::apply_time_shift(&mut target, time_shift);
-    let note_shift = align::compute_note_shift(&target, input);
-    align::apply_note_shift(&mut target, note_shift);
-    let time_shift_secs = *input_series.notes().interval() * core::isize_to_f64(time_shift);
+    let key_shift = align::compute_note_shift(&target, input).ok_or(RunError::NoteOverlapEmpty)?;
+    align::apply_note_shift(&mut target, key_shift.semitones);
+    let aligned = align_locally(&mut target, input, dt, time_shift_secs, key_shift, options)?;
+    let mut accuracy = Accuracy::new(
+        &target,
+        input_series.notes(),
+        &aligned.timing_deviations,
+        aligned.key_shift,
+        aligned.phrases,
+        tempo_ratio,
+        &options.grade,
+    );
+    for note in &mut accuracy.notes {
+        note.position = note
+            .index
+            .and_then(|index| target_unpadded_raw.note_position(index));
+    }
     #[cfg(feature = "visualise")]
-    crate::visualise::plot(&target, input_series.notes(), fp)?;
-    Ok(Accuracy::new(&target, input, time_shift_secs, note_shift))
+    crate::visualise::plot(
+        &target,
+        input_series.notes(),
+        options.grade.low_confidence_threshold,
+        &accuracy.notes,
+        fp,
+    )?;
+    Ok(accuracy)
+}
+
+/// How the target lines up with the singing once it is aligned locally.
+struct LocalAlignment {
+    /// Seconds that every frame of the singing was sung later than the target at the tempo it
+    /// was sung in.
+    timing_deviations: Box<[f64]>,
+    key_shift: align::KeyShift,
+    phrases: Box<[PhraseOffset]>,
+}
+
+/// Aligns the phrases of the shifted target and warps it onto the singing as `options` choose.
+fn align_locally(
+    target: &mut core::NoteSeries,
+    input: &core::NoteSeries,
+    dt: f64,
+    time_shift_secs: f64,
+    mut key_shift: align::KeyShift,
+    options: &Options,
+) -> Result<LocalAlignment, RunError> {
+    // seconds that every frame of the target was moved later by
+    let mut lateness = vec![-time_shift_secs; target.len()].into_boxed_slice();
+    let mut phrases = Vec::new();
+    if let Some(phrase) = &options.phrases {
+        let min_rest = core::f64_to_usize((phrase.min_rest_secs / dt).round()).max(1);
+        let search = core::f64_to_usize((phrase.search_secs / dt).round());
+        let lags;
+        (phrases, lags) = align::align_phrases(target, input, min_rest, search);
+        for (late, &lag) in lateness.iter_mut().zip(&lags) {
+            *late = dt.mul_add(core::isize_to_f64(lag), -time_shift_secs);
+        }
+    }
+
+    let mut path = (0..target.len()).collect::<Box<_>>();
+    if let Some(warp) = &options.warp {
+        let band = core::f64_to_usize((warp.band_secs / dt).round());
+        path = align::compute_warp(target, input, band, warp.step_pattern);
+        align::apply_warp(target, &path);
+        let warped_key_shift =
+            align::compute_note_shift(target, input).ok_or(RunError::NoteOverlapEmpty)?;
+        align::apply_note_shift(target, warped_key_shift.semitones);
+        key_shift = align::KeyShift {
+            semitones: key_shift.semitones + warped_key_shift.semitones,
+            ..warped_key_shift
+        };
+    }
+
+    let timing_deviations = path
+        .iter()
+        .enumerate()
+        .map(|(i, &j)| dt.mul_add(core::usize_to_f64(i) - core::usize_to_f64(j), lateness[j]))
+        .collect();
+    let phrases = phrases
+        .into_iter()
+        .map(|phrase| PhraseOffset {
+            range: dt * core::usize_to_f64(phrase.frames.start)
+                ..dt * core::usize_to_f64(phrase.frames.end),
+            late_secs: dt.mul_add(core::isize_to_f64(phrase.lag), -time_shift_secs),
+            key_shift: key_shift.semitones + phrase.key_shift,
+        })
+        .collect();
+    Ok(LocalAlignment {
+        timing_deviations,
+        key_shift,
+        phrases,
+    })
+}
+
+/// Transcribes the singing in `wav_file` into the standard MIDI file `midi_file`, analysing it
+/// as [`run_with_options`] does.
+///
+/// # Errors
+/// - opening the wav file failed
+/// - writing the MIDI file failed
+pub fn transcribe<P: AsRef<Path>>(
+    wav_file: P,
+    midi_file: P,
+    options: &Options,
+    transcribe: &core::TranscribeOptions,
+) -> Result<(), RunError> {
+    analyse(wav_file, options)?
+        .to_midi(transcribe)?
+        .save(midi_file)?;
+    Ok(())
 }
 
 #[cfg(feature = "visualise")]
-fn plot_target_file<P: AsRef<Path>>(midi_file: &P, wav_file: &P) -> String {
-    let midi_file_stem = midi_file.as_ref().file_stem().expect("no file stem");
+fn plot_target_file<P: AsRef<Path>>(target_file: &P, wav_file: &P) -> String {
+    let target_file_stem = target_file.as_ref().file_stem().expect("no file stem");
     let wav_file_stem = wav_file.as_ref().file_stem().expect("no file stem");
     format!(
         "{}+{}.png",
-        midi_file_stem.to_str().expect("utf-8 file stem"),
+        target_file_stem.to_str().expect("utf-8 file stem"),
         wav_file_stem.to_str().expect("utf-8 file stem")
     )
 }
@@ -53,18 +273,22 @@ mod test {
 
     use rstest::rstest;
 
-    use super::run;
+    use super::{Options, run, run_with_options};
+    use crate::{PhraseOptions, TempoOptions, WarpOptions, error::RunError};
 
     #[rstest]
     #[case("test.mid", "test.wav", 0.8..1.0)]
     #[case("test.mid", "100hz-4s.wav", 0.0..0.2)]
     #[case("bite.mid", "bite-cn.wav", 0.6..0.8)]
-    #[case("bite.mid", "bite-cn-delayed-100ms.wav", 0.0..0.2)]
+    // `bite-cn` is sung about 70 ms early, so delaying it by 100 ms brings it in time
+    #[case("bite.mid", "bite-cn-delayed-100ms.wav", 0.7..0.9)]
     #[case("bite.mid", "bite-cn-delayed-6.1s.wav", 0.0..0.2)]
-    #[case("bite-2.mid", "bite-kr.wav", 0.6..0.8)]
+    #[case("bite-2.mid", "bite-kr.wav", 0.8..0.9)]
     #[case("bite-2.mid", "bite-jp.wav", 0.0..0.2)]
     #[case("tetris.mid", "tetris.wav", 0.8..1.0)]
     #[case("tetris.mid", "tetris-2.wav", 0.8..1.0)]
+    #[case("../test/tetris.wav", "tetris-2.wav", 0.8..1.0)]
+    #[case("../test/tetris.wav", "bite-jp.wav", 0.0..0.2)]
     fn test<P: AsRef<Path>, R: RangeBounds<f64>>(
         #[case] midi_file: P,
         #[case] wav_file: P,
@@ -77,4 +301,116 @@ mod test {
         .expect("running failed");
         assert!(expected_accuracy.contains(&accuracy.total_accuracy()));
     }
+
+    #[rstest]
+    #[case("test.mid", "test.wav", 0.9..=1.0)]
+    #[case("bite-2.mid", "bite-jp.wav", 0.8..=1.0)]
+    fn warp<P: AsRef<Path>, R: RangeBounds<f64>>(
+        #[case] midi_file: P,
+        #[case] wav_file: P,
+        #[case] expected_pitch: R,
+    ) {
+        let options = Options {
+            warp: Some(WarpOptions::default()),
+            ..Options::default()
+        };
+        let accuracy = run_with_options(
+            Path::new("../midi").join(midi_file),
+            Path::new("../test").join(wav_file),
+            &options,
+        )
+        .expect("running failed");
+        assert!(expected_pitch.contains(&accuracy.pitch));
+    }
+
+    #[rstest]
+    #[case("tetris.mid", "tetris.wav", -0.1..0.1)]
+    #[case("bite-2.mid", "bite-jp.wav", 0.5..1.5)]
+    fn phrases<P: AsRef<Path>, R: RangeBounds<f64>>(
+        #[case] midi_file: P,
+        #[case] wav_file: P,
+        #[case] expected_drift: R,
+    ) {
+        let options = Options {
+            phrases: Some(PhraseOptions::default()),
+            ..Options::default()
+        };
+        let accuracy = run_with_options(
+            Path::new("../midi").join(midi_file),
+            Path::new("../test").join(wav_file),
+            &options,
+        )
+        .expect("running failed");
+        let (Some(first), Some(last)) = (accuracy.phrases.first(), accuracy.phrases.last()) else {
+            panic!("no phrases found");
+        };
+        assert!(expected_drift.contains(&(last.late_secs - first.late_secs)));
+    }
+
+    #[rstest]
+    #[case("tetris.mid", "tetris.wav", 0.99..1.01)]
+    #[case("bite-2.mid", "bite-kr.wav", 0.94..0.98)]
+    fn tempo<P: AsRef<Path>, R: RangeBounds<f64>>(
+        #[case] midi_file: P,
+        #[case] wav_file: P,
+        #[case] expected_ratio: R,
+    ) {
+        let options = Options {
+            tempo: Some(TempoOptions::default()),
+            ..Options::default()
+        };
+        let accuracy = run_with_options(
+            Path::new("../midi").join(midi_file),
+            Path::new("../test").join(wav_file),
+            &options,
+        )
+        .expect("running failed");
+        assert!(expected_ratio.contains(&accuracy.tempo_ratio));
+    }
+
+    #[test]
+    fn invalid_tempo_range() {
+        let options = Options {
+            tempo: Some(TempoOptions {
+                min_ratio: 0.,
+                max_ratio: 1.1,
+            }),
+            ..Options::default()
+        };
+        let result = run_with_options(
+            Path::new("../midi/tetris.mid"),
+            Path::new("../test/tetris.wav"),
+            &options,
+        );
+        assert!(matches!(result, Err(RunError::InvalidTempoRange { .. })));
+    }
+
+    #[rstest]
+    #[case(0.15)]
+    #[case(0.3)]
+    fn late(#[case] delay_secs: f64) {
+        // a copy of the take that starts `delay_secs` later and stays as late throughout
+        let on_time = Path::new("../test/tetris.wav");
+        let mut wav = hound::WavReader::open(on_time).expect("opening wav failed");
+        let spec = wav.spec();
+        let late = std::env::temp_dir().join(format!("cantometria-late-{delay_secs}.wav"));
+        let mut writer = hound::WavWriter::create(&late, spec).expect("creating wav failed");
+        let silence = crate::core::f64_to_usize(delay_secs * f64::from(spec.sample_rate))
+            * usize::from(spec.channels);
+        for _ in 0..silence {
+            writer.write_sample(0i16).expect("writing wav failed");
+        }
+        for sample in wav.samples::<i16>() {
+            writer
+                .write_sample(sample.expect("reading wav failed"))
+                .expect("writing wav failed");
+        }
+        writer.finalize().expect("writing wav failed");
+
+        let midi_file = Path::new("../midi/tetris.mid");
+        let on_time = run(midi_file, on_time).expect("running failed");
+        let late = run(midi_file, &late).expect("running failed");
+        assert!(late.timing < on_time.timing);
+        assert!(late.total_accuracy() < on_time.total_accuracy());
+    }
 }
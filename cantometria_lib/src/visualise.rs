@@ -5,16 +5,19 @@ use ordered_float::FloatCore;
 use crate::{
     core::{NoteSeries, NoteTimeSeries, Time, usize_to_f64},
     error::PlotError,
+    grade::NoteAccuracy,
 };
 pub fn plot<P: AsRef<Path>>(
     target_series: &NoteSeries,
     input_time_series: &NoteTimeSeries,
+    low_confidence_threshold: f64,
+    notes: &[NoteAccuracy],
     file: P,
 ) -> Result<(), PlotError> {
     use plotters::{
         chart::ChartBuilder,
-        prelude::{BitMapBackend, Circle, IntoDrawingArea},
-        style::{BLACK, BLUE, Color, RED, WHITE},
+        prelude::{BitMapBackend, Circle, IntoDrawingArea, PathElement},
+        style::{BLACK, BLUE, Color, GREEN, MAGENTA, RED, WHITE},
     };
 
     const CIRCLE_SIZE: f64 = 2.;
@@ -26,16 +29,29 @@ pub fn plot<P: AsRef<Path>>(
         .filter_map(|(i, ts)| ts.as_ref().map(|ns| (dt * usize_to_f64(i), ns.note_number)))
         .collect::<Box<_>>();
 
-    let input_data = input_time_series
+    let (input_data, uncertain_input_data): (Vec<_>, Vec<_>) = input_time_series
         .iter()
         .enumerate()
-        .filter_map(|(i, ts)| ts.as_ref().map(|ns| (dt * usize_to_f64(i), ns.note_number)))
+        .filter_map(|(i, ts)| {
+            ts.as_ref().map(|ns| {
+                (
+                    (dt * usize_to_f64(i), ns.note_number),
+                    ns.clarity() < low_confidence_threshold,
+                )
+            })
+        })
+        .partition(|(_, uncertain)| !uncertain);
+    let input_data = input_data.into_iter().map(|(p, _)| p).collect::<Box<_>>();
+    let uncertain_input_data = uncertain_input_data
+        .into_iter()
+        .map(|(p, _)| p)
         .collect::<Box<_>>();
 
     let all_x = target_data
         .iter()
         .map(|(x, _)| *x)
         .chain(input_data.iter().map(|(x, _)| *x))
+        .chain(uncertain_input_data.iter().map(|(x, _)| *x))
         .collect::<Box<_>>();
     let x_min = all_x.iter().copied().fold(Time::infinity(), Ord::min);
     let x_max = all_x.iter().copied().fold(Time::neg_infinity(), Ord::max);
@@ -44,6 +60,7 @@ pub fn plot<P: AsRef<Path>>(
         .iter()
         .map(|(_, y)| *y)
         .chain(input_data.iter().map(|(_, y)| *y))
+        .chain(uncertain_input_data.iter().map(|(_, y)| *y))
         .collect();
     let y_min = all_y.iter().copied().fold(f64::INFINITY, f64::min);
     let y_max = all_y.iter().copied().fold(f64::NEG_INFINITY, f64::max);
@@ -83,7 +100,50 @@ pub fn plot<P: AsRef<Path>>(
         .label("I[n]")
         .legend(|(x, y)| Circle::new((x + 10, y), CIRCLE_SIZE, BLUE.filled()));
 
+    chart
+        .draw_series(
+            uncertain_input_data
+                .iter()
+                .map(|(x, y)| Circle::new((**x, *y), CIRCLE_SIZE, BLUE.mix(0.25).filled())),
+        )?
+        .label("I[n] (low confidence)")
+        .legend(|(x, y)| Circle::new((x + 10, y), CIRCLE_SIZE, BLUE.mix(0.25).filled()));
+
+    let (in_tune, off_pitch) = sung_segments(notes);
+    for (notes, color, label) in [
+        (in_tune, GREEN, "median sung"),
+        (off_pitch, MAGENTA, "median sung (off pitch)"),
+    ] {
+        chart
+            .draw_series(
+                notes
+                    .into_iter()
+                    .map(|[start, end]| PathElement::new([start, end], color.stroke_width(2))),
+            )?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+
     chart.configure_series_labels().border_style(BLACK).draw()?;
 
     Ok(())
 }
+
+type Segment = [(f64, f64); 2];
+
+/// Splits the median sung pitch of every sung note into the notes sung in tune and off pitch.
+fn sung_segments(notes: &[NoteAccuracy]) -> (Vec<Segment>, Vec<Segment>) {
+    /// Cents that the median sung pitch of a note may be off by before it is drawn as off pitch.
+    const OFF_PITCH_CENTS: f64 = 50.;
+
+    let (in_tune, off_pitch): (Vec<_>, Vec<_>) = notes
+        .iter()
+        .filter_map(|n| {
+            let sung = n.sung?;
+            let in_tune = n.cents.is_none_or(|c| c.abs() < OFF_PITCH_CENTS);
+            Some(([(n.range.start, sung), (n.range.end, sung)], in_tune))
+        })
+        .partition(|(_, in_tune)| *in_tune);
+    let segments = |notes: Vec<(Segment, bool)>| notes.into_iter().map(|(s, _)| s).collect();
+    (segments(in_tune), segments(off_pitch))
+}
@@ -16,7 +16,10 @@ pub struct App {
     pub current_screen: CurrentScreen,
     pub midi_path_list: PathList,
     pub wav_path_list: PathList,
-    pub accuracies: HashMap<(PathBuf, PathBuf), Accuracy>,
+    /// Grades of every pair of target and recording graded so far, or why grading them failed.
+    pub accuracies: HashMap<(PathBuf, PathBuf), Result<Accuracy, String>>,
+    /// MIDI file the graded recording was last transcribed into, or why transcribing it failed.
+    pub exported: Option<Result<PathBuf, String>>,
 }
 
 impl App {
@@ -28,7 +31,11 @@ impl App {
                 .into_iter()
                 .filter_map(|x| {
                     x.ok().filter(|y| {
-                        y.file_type().is_file() && y.path().extension().is_some_and(|z| z == "mid")
+                        y.file_type().is_file()
+                            && has_extension(
+                                y,
+                                &["mid", "midi", "kar", "txt", "musicxml", "xml", "mxl", "wav"],
+                            )
                     })
                 })
                 .collect(),
@@ -36,16 +43,24 @@ impl App {
                 .follow_links(true)
                 .into_iter()
                 .filter_map(|x| {
-                    x.ok().filter(|y| {
-                        y.file_type().is_file() && y.path().extension().is_some_and(|z| z == "wav")
-                    })
+                    x.ok()
+                        .filter(|y| y.file_type().is_file() && has_extension(y, &["wav"]))
                 })
                 .collect(),
             accuracies: HashMap::new(),
+            exported: None,
         }
     }
 }
 
+/// Whether the file of `entry` has any of `extensions`, in any case.
+fn has_extension(entry: &DirEntry, extensions: &[&str]) -> bool {
+    entry
+        .path()
+        .extension()
+        .is_some_and(|e| extensions.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
+
 pub struct PathList {
     pub items: Box<[DirEntry]>,
     pub state: ListState,
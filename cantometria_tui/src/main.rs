@@ -1,6 +1,10 @@
-use std::error::Error;
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
 
 use app::{App, CurrentScreen};
+use cantometria_lib::{Options, TranscribeOptions};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     execute,
@@ -70,14 +74,49 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<(),
                     KeyCode::Right => app.current_screen = CurrentScreen::Grading,
                     _ => {}
                 },
-                CurrentScreen::Grading => {
-                    if key.code == KeyCode::Right {
+                CurrentScreen::Grading => match key.code {
+                    KeyCode::Right => {
                         app.current_screen = CurrentScreen::Main;
                         app.midi_path_list.state.select(None);
                         app.wav_path_list.state.select(None);
+                        app.exported = None;
+                    }
+                    KeyCode::Char('e') => {
+                        if let Some(wav_path_idx) = app.wav_path_list.state.selected() {
+                            let wav_file = app.wav_path_list.items[wav_path_idx].path();
+                            let midi_file = export_path(wav_file);
+                            let options = TranscribeOptions {
+                                pitch_bend: true,
+                                ..TranscribeOptions::default()
+                            };
+                            app.exported = Some(
+                                cantometria_lib::transcribe(
+                                    wav_file,
+                                    &midi_file,
+                                    &Options::default(),
+                                    &options,
+                                )
+                                .map(|()| midi_file)
+                                .map_err(|e| e.to_string()),
+                            );
+                        }
                     }
-                }
+                    _ => {}
+                },
             }
         }
     }
 }
+
+/// Names the MIDI file that `wav_file` is transcribed into, next to it and numbered so that no
+/// existing file is overwritten.
+fn export_path(wav_file: &Path) -> PathBuf {
+    let stem = wav_file.file_stem().unwrap_or_default().to_string_lossy();
+    let mut path = wav_file.with_extension("mid");
+    let mut n = 1;
+    while path.exists() {
+        path = wav_file.with_file_name(format!("{stem}-{n}.mid"));
+        n += 1;
+    }
+    path
+}
@@ -9,6 +9,8 @@ use ratatui::{
 };
 use tui_big_text::{BigText, PixelSize};
 
+use cantometria_lib::{Accuracy, NoteAccuracy};
+
 use crate::app::{App, CurrentScreen};
 
 pub fn ui(frame: &mut Frame, app: &mut App) {
@@ -102,7 +104,16 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
             " / ".into(),
             "(→) to confirm and analyse".magenta(),
         ])),
-        CurrentScreen::Grading => Paragraph::new("(→) to return to main menu".cyan()),
+        CurrentScreen::Grading => Paragraph::new(Line::from(vec![
+            match (grading_error(app), &app.exported) {
+                (Some(e), _) => format!("grading failed: {e}").red(),
+                (None, None) => "(e) to export your singing as MIDI".yellow(),
+                (None, Some(Ok(path))) => format!("exported to {}", path.display()).green(),
+                (None, Some(Err(e))) => format!("export failed: {e}").red(),
+            },
+            " / ".into(),
+            "(→) to return to main menu".cyan(),
+        ])),
     }
     .centered()
     .block(footer_block);
@@ -123,6 +134,17 @@ fn color_grade(grade: f64) -> Color {
     }
 }
 
+/// Why grading the selected target and recording failed, if it did.
+fn grading_error(app: &App) -> Option<&str> {
+    let midi_file = app.midi_path_list.items[app.midi_path_list.state.selected()?].path();
+    let wav_file = app.wav_path_list.items[app.wav_path_list.state.selected()?].path();
+    app.accuracies
+        .get(&(midi_file.to_path_buf(), wav_file.to_path_buf()))?
+        .as_ref()
+        .err()
+        .map(String::as_str)
+}
+
 fn render_grading(frame: &mut Frame<'_>, app: &mut App, chunks: &[Rect]) {
     let (Some(midi_path_idx), Some(wav_path_idx)) = (
         app.midi_path_list.state.selected(),
@@ -138,6 +160,32 @@ fn render_grading(frame: &mut Frame<'_>, app: &mut App, chunks: &[Rect]) {
         .border_style(Style::new().cyan());
     frame.render_widget(block, chunks[1]);
 
+    let midi_file = app.midi_path_list.items[midi_path_idx].path();
+    let wav_file = app.wav_path_list.items[wav_path_idx].path();
+    let Ok(accuracy) = app
+        .accuracies
+        .entry((midi_file.to_path_buf(), wav_file.to_path_buf()))
+        .or_insert_with(|| cantometria_lib::run(midi_file, wav_file).map_err(|e| e.to_string()))
+    else {
+        return;
+    };
+
+    let chunks_details = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+        .split(chunks[1].inner(Margin::new(1, 2)));
+    let grade_area = chunks_details[0];
+    if accuracy.lines.is_empty() {
+        render_notes(frame, accuracy, chunks_details[1]);
+    } else {
+        let chunks_lyrics = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+            .split(chunks_details[1]);
+        render_lyrics(frame, accuracy, chunks_lyrics[0]);
+        render_notes(frame, accuracy, chunks_lyrics[1]);
+    }
+
     let chunks_grade = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -145,14 +193,7 @@ fn render_grading(frame: &mut Frame<'_>, app: &mut App, chunks: &[Rect]) {
             Constraint::Ratio(2, 6),
             Constraint::Ratio(3, 6),
         ])
-        .split(chunks[1].inner(Margin::new(1, 2)));
-
-    let midi_file = app.midi_path_list.items[midi_path_idx].path();
-    let wav_file = app.wav_path_list.items[wav_path_idx].path();
-    let accuracy = app
-        .accuracies
-        .entry((midi_file.to_path_buf(), wav_file.to_path_buf()))
-        .or_insert_with(|| cantometria_lib::run(midi_file, wav_file).expect("msg"));
+        .split(grade_area);
 
     let desc = BigText::builder()
         .pixel_size(PixelSize::Sextant)
@@ -197,6 +238,111 @@ fn render_grading(frame: &mut Frame<'_>, app: &mut App, chunks: &[Rect]) {
     frame.render_widget(accuracy_txt, chunks_grade[2]);
 }
 
+/// Cents that a syllable or a note may be sung off pitch by before it is pointed out.
+const CENTS_THRESHOLD: f64 = 20.;
+/// Seconds that a note may be started early or late by before it is pointed out.
+const ONSET_THRESHOLD: f64 = 0.1;
+
+/// Describes the worst sung syllable of a line of the lyrics, if any was missed or off pitch.
+fn lyric_hint(accuracy: &Accuracy, line: usize) -> Option<String> {
+    let syllables = accuracy.syllables.iter().filter(|s| s.line == line);
+    if let Some(missed) = syllables.clone().find(|s| s.coverage == 0.) {
+        return Some(format!("missed '{}'", missed.text));
+    }
+    let (syllable, cents) = syllables
+        .filter_map(|s| Some((s, s.cents?)))
+        .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))?;
+    (cents.abs() >= CENTS_THRESHOLD).then(|| {
+        let direction = if cents < 0. { "flat" } else { "sharp" };
+        format!(
+            "{:.0} cents {direction} on '{}'",
+            cents.abs(),
+            syllable.text
+        )
+    })
+}
+
+fn render_lyrics(frame: &mut Frame<'_>, accuracy: &Accuracy, area: Rect) {
+    let lines = accuracy
+        .lines
+        .iter()
+        .map(|line| {
+            let pitch = line.pitch.unwrap_or_default();
+            let mut spans = vec![
+                format!("{:>3} ", line.line + 1).into(),
+                format!("{:>6.2} ", pitch.mul(100.)).fg(color_grade(pitch)),
+                line.text.clone().into(),
+            ];
+            if let Some(hint) = lyric_hint(accuracy, line.line) {
+                spans.push(format!("  ({hint})").italic());
+            }
+            Line::from(spans)
+        })
+        .collect::<Vec<_>>();
+    let lyrics = Paragraph::new(lines).block(
+        Block::new()
+            .title(Line::raw("Lyrics").centered())
+            .border_style(Style::new().cyan())
+            .borders(Borders::ALL),
+    );
+    frame.render_widget(lyrics, area);
+}
+
+/// Names the nearest note to a note number, such as `C#4` for middle C sharp.
+#[allow(clippy::cast_possible_truncation)]
+fn note_name(note_number: f64) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    let note_number = note_number.round() as i64;
+    let name = usize::try_from(note_number.rem_euclid(12)).map_or("?", |i| NAMES[i]);
+    format!("{name}{}", note_number.div_euclid(12) - 1)
+}
+
+/// Describes how a note was sung, if it was missed, off pitch or started early or late.
+fn note_hint(note: &NoteAccuracy) -> Option<String> {
+    if note.coverage == 0. {
+        return Some("missed".to_owned());
+    }
+    let mut hints = Vec::new();
+    if let Some(cents) = note.cents.filter(|c| c.abs() >= CENTS_THRESHOLD) {
+        let direction = if cents < 0. { "flat" } else { "sharp" };
+        hints.push(format!("{:.0} cents {direction}", cents.abs()));
+    }
+    if let Some(onset) = note.onset.filter(|o| o.abs() >= ONSET_THRESHOLD) {
+        let direction = if onset < 0. { "early" } else { "late" };
+        hints.push(format!("{:.2}s {direction}", onset.abs()));
+    }
+    (!hints.is_empty()).then(|| hints.join(", "))
+}
+
+fn render_notes(frame: &mut Frame<'_>, accuracy: &Accuracy, area: Rect) {
+    let lines = accuracy
+        .notes
+        .iter()
+        .filter_map(|note| {
+            let hint = note_hint(note)?;
+            let grade = note.pitch.unwrap_or(note.coverage);
+            // blank unless the target is a MIDI file or a score
+            let bar_beat = note
+                .position
+                .map_or_else(String::new, |p| p.bar_beat.to_string());
+            Some(Line::from(vec![
+                format!("{:>7.2}s {bar_beat:>8} ", note.range.start).into(),
+                format!("{:<4}", note_name(note.note_number)).fg(color_grade(grade)),
+                format!("  {hint}").italic(),
+            ]))
+        })
+        .collect::<Vec<_>>();
+    let notes = Paragraph::new(lines).block(
+        Block::new()
+            .title(Line::raw("Notes").centered())
+            .border_style(Style::new().cyan())
+            .borders(Borders::ALL),
+    );
+    frame.render_widget(notes, area);
+}
+
 fn render_select_midi(frame: &mut Frame<'_>, app: &mut App, chunks: &[Rect]) {
     let chunks_sel = Layout::default()
         .direction(Direction::Vertical)
@@ -204,7 +350,7 @@ fn render_select_midi(frame: &mut Frame<'_>, app: &mut App, chunks: &[Rect]) {
         .split(chunks[1]);
 
     let desc = Paragraph::new(
-        "1. Select the MIDI file of the track of the reference melody or song used during singing.",
+        "1. Select the MIDI file, UltraStar song, MusicXML score or reference recording of the melody used during singing.",
     )
     .bold()
     .centered()
@@ -214,7 +360,7 @@ fn render_select_midi(frame: &mut Frame<'_>, app: &mut App, chunks: &[Rect]) {
     frame.render_widget(desc, chunks_sel[0]);
 
     let list_block = Block::new()
-        .title(Line::raw("MIDI File List").centered())
+        .title(Line::raw("Target File List").centered())
         .border_style(Style::new().green())
         .borders(Borders::ALL);
 
@@ -291,7 +437,7 @@ fn render_confirm_select(frame: &mut Frame<'_>, app: &App, chunks: &[Rect]) {
         .split(chunks_confirm[1]);
 
     let sel_midi_file_block = Block::new()
-        .title(Line::raw("Selected MIDI File").centered())
+        .title(Line::raw("Selected Target File").centered())
         .border_style(Style::new().green())
         .borders(Borders::ALL)
         .padding(Padding::new(0, 0, chunks_confirm_mid[0].height / 3, 0));